@@ -0,0 +1,108 @@
+//! cyrly against serde_yaml on a few representative documents
+//!
+//! Throughput is in bytes of output, and each serializer's output size is printed before the measurements.
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion, Throughput};
+use serde_json::{json, Value};
+
+fn kubernetes_manifests() -> Value {
+    (0..200)
+        .map(|i| {
+            json!({
+                "apiVersion": "apps/v1",
+                "kind": "Deployment",
+                "metadata": {
+                    "name": format!("service-{i}"),
+                    "namespace": "production",
+                    "labels": { "app": format!("service-{i}"), "tier": "backend" },
+                    "annotations": { "deployment.kubernetes.io/revision": "3" },
+                },
+                "spec": {
+                    "replicas": i % 5 + 1,
+                    "selector": { "matchLabels": { "app": format!("service-{i}") } },
+                    "template": {
+                        "metadata": { "labels": { "app": format!("service-{i}") } },
+                        "spec": {
+                            "containers": [{
+                                "name": "main",
+                                "image": format!("registry.example.com/service-{i}:1.{i}.0"),
+                                "args": ["--port", "8080", "--log-level", "info"],
+                                "ports": [{ "containerPort": 8080, "protocol": "TCP" }],
+                                "env": [
+                                    { "name": "RUST_LOG", "value": "info" },
+                                    { "name": "ENABLE_CACHE", "value": "yes" },
+                                ],
+                                "resources": {
+                                    "limits": { "cpu": "500m", "memory": "256Mi" },
+                                    "requests": { "cpu": "100m", "memory": "128Mi" },
+                                },
+                                "readinessProbe": {
+                                    "httpGet": { "path": "/healthz", "port": 8080 },
+                                    "initialDelaySeconds": 5,
+                                },
+                            }],
+                        },
+                    },
+                },
+            })
+        })
+        .collect()
+}
+
+fn numbers() -> Value {
+    (0..50_000u32)
+        .map(|i| match i % 3 {
+            0 => json!(i),
+            1 => json!(-i64::from(i) * 7919),
+            _ => json!(f64::from(i) / 13.0),
+        })
+        .collect()
+}
+
+fn prose() -> Value {
+    let paragraph = "It was the best of times, it was the worst of times, \
+        it was the age of wisdom, it was the age of foolishness, \
+        it was the epoch of belief, it was the epoch of incredulity, \
+        it was the season of Light, it was the season of Darkness.\n\
+        It was the spring of hope, it was the winter of despair.";
+    (0..500)
+        .map(|i| json!({ "chapter": i, "text": paragraph.repeat(1 + i % 4) }))
+        .collect()
+}
+
+fn nested_maps() -> Value {
+    (0..100).fold(json!("bottom"), |inner, i| {
+        json!({ "level": i, "name": format!("node {i}"), "child": inner, "sibling": [i, i + 1] })
+    })
+}
+
+fn compare(c: &mut Criterion) {
+    let corpora = [
+        ("kubernetes", kubernetes_manifests()),
+        ("numbers", numbers()),
+        ("prose", prose()),
+        ("nested", nested_maps()),
+    ];
+    for (name, data) in &corpora {
+        let cyrly = cyrly::to_string(data).unwrap().len();
+        let yaml = serde_yaml::to_string(data).unwrap().len();
+        eprintln!("{name}: cyrly {cyrly} bytes, serde_yaml {yaml} bytes");
+
+        let mut group = c.benchmark_group(*name);
+        group.throughput(Throughput::Bytes(cyrly as u64));
+        group.bench_function("cyrly::to_string", |b| {
+            b.iter(|| cyrly::to_string(black_box(data)).unwrap())
+        });
+        group.bench_function("cyrly::to_writer", |b| {
+            b.iter(|| cyrly::to_writer(std::io::sink(), black_box(data)).unwrap())
+        });
+        group.throughput(Throughput::Bytes(yaml as u64));
+        group.bench_function("serde_yaml::to_string", |b| {
+            b.iter(|| serde_yaml::to_string(black_box(data)).unwrap())
+        });
+        group.finish();
+    }
+}
+
+criterion_group!(benches, compare);
+criterion_main!(benches);
@@ -0,0 +1,83 @@
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use serde::ser::{SerializeMap, Serializer};
+use serde::Serialize;
+
+#[derive(Serialize)]
+struct LogLine {
+    timestamp: u64,
+    level: char,
+    thread: u32,
+    latency: f64,
+    status: u16,
+    ok: bool,
+    message: String,
+}
+
+fn log_lines() -> Vec<LogLine> {
+    (0..10_000u32)
+        .map(|i| LogLine {
+            timestamp: 1_680_000_000_000 + u64::from(i) * 17,
+            level: ['I', 'W', 'E'][i as usize % 3],
+            thread: i % 16,
+            latency: f64::from(i) / 7.0,
+            status: [200, 404, 500][i as usize % 3],
+            ok: i % 5 != 0,
+            message: format!("request {i} handled"),
+        })
+        .collect()
+}
+
+fn scalars(c: &mut Criterion) {
+    let data = log_lines();
+    let len = cyrly::to_string(&data).unwrap().len();
+    let mut group = c.benchmark_group("scalars");
+    group.throughput(Throughput::Bytes(len as u64));
+    group.bench_function("to_string", |b| {
+        b.iter(|| cyrly::to_string(black_box(&data)).unwrap())
+    });
+    group.finish();
+}
+
+/// Map with a single entry, whose key is a map with a single entry, whose key…
+struct NestedKeys(usize);
+
+impl Serialize for NestedKeys {
+    fn serialize<S: Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+        if self.0 == 0 {
+            return s.serialize_str("a string long enough that no nested key fits on one line");
+        }
+        let mut map = s.serialize_map(Some(1))?;
+        map.serialize_entry(&NestedKeys(self.0 - 1), &self.0)?;
+        map.end()
+    }
+}
+
+fn nested_keys(depth: usize, multiline: bool) -> String {
+    let mut out = String::new();
+    let ser = cyrly::CurlySerializer::new(&mut out);
+    let ser = if multiline {
+        ser.multiline()
+    } else {
+        ser.oneline()
+    };
+    NestedKeys(depth).serialize(ser).unwrap();
+    out
+}
+
+/// Time per nesting level should stay flat as depth grows
+/// (except for multiline output, which has quadratically many indentation spaces)
+fn nesting(c: &mut Criterion) {
+    let mut group = c.benchmark_group("nesting");
+    for depth in [100, 300, 1000] {
+        group.throughput(Throughput::Elements(depth as u64));
+        for (name, multiline) in [("keys/multiline", true), ("keys/oneline", false)] {
+            group.bench_with_input(BenchmarkId::new(name, depth), &depth, |b, &depth| {
+                b.iter(|| nested_keys(black_box(depth), multiline))
+            });
+        }
+    }
+    group.finish();
+}
+
+criterion_group!(benches, scalars, nesting);
+criterion_main!(benches);
@@ -1,14 +1,181 @@
-use cyrly::CurlySerializer;
+use cyrly::{write::WriteEat, AnsiEat, CurlySerializer, SeqMerge, Value};
 use serde::Serialize;
-use serde_yaml::Value;
+mod path;
+
 use std::{
     error::Error,
-    io::{stdin, stdout, BufWriter},
+    fs::{self, File},
+    io::{stdin, stdout, BufWriter, IsTerminal, Write},
 };
 
-fn main() -> Result<(), Box<dyn Error>> {
-    serde_yaml::from_reader::<_, Value>(stdin())?.serialize(CurlySerializer::new(
-        &mut cyrly::write::WriteEat(BufWriter::new(stdout())),
-    ))?;
-    Ok(())
+const USAGE: &str = "usage: cyrly [--color=auto|always|never] < in.yaml
+       cyrly [--color=auto|always|never] diff a.yaml b.yaml
+       cyrly [--color=auto|always|never] [--append|--merge-by=KEY] merge base.yaml over.yaml…
+       cyrly [--color=auto|always|never] [--raw] get spec.containers[0].image [in.yaml]
+       cyrly [--color=auto|always|never] set spec.replicas '3' [in.yaml]
+       cyrly [--color=auto|always|never] delete spec.replicas [in.yaml]
+
+set and delete write back to in.yaml if it's given, set's value is read as YAML,
+which takes cyrly output like '{ a: [1, 2] }' as well as block YAML like 'a: b'.";
+
+fn main() {
+    // Through Display, the Debug of Box<dyn Error> would show messages quoted and escaped
+    if let Err(e) = run() {
+        eprintln!("Error: {e}");
+        std::process::exit(1)
+    }
+}
+
+fn usage() -> ! {
+    eprintln!("{USAGE}");
+    std::process::exit(2)
+}
+
+fn run() -> Result<(), Box<dyn Error>> {
+    let mut color = None;
+    let mut seqs = SeqMerge::Replace;
+    let mut raw = false;
+    let mut args = Vec::new();
+    for arg in std::env::args().skip(1) {
+        match arg.as_str() {
+            "--color" | "--color=always" => color = Some(true),
+            "--color=never" => color = Some(false),
+            "--color=auto" => color = None,
+            "--append" => seqs = SeqMerge::Append,
+            "--raw" => raw = true,
+            _ if arg.starts_with("--merge-by=") => {
+                seqs = SeqMerge::ByKey(arg["--merge-by=".len()..].to_owned())
+            }
+            _ if arg.starts_with("--") => usage(),
+            _ => args.push(arg),
+        };
+    }
+    let color = color.unwrap_or_else(|| {
+        stdout().is_terminal() && std::env::var_os("NO_COLOR").unwrap_or_default().is_empty()
+    });
+
+    match args.iter().map(String::as_str).collect::<Vec<_>>()[..] {
+        [] => print(&mut stdout(), &read("-")?, color),
+        ["diff", a, b] => {
+            let diff = cyrly::diff(&read(a)?, &read(b)?)?;
+            let mut out = BufWriter::new(stdout());
+            for line in diff.lines() {
+                match (color, line.as_bytes()[0]) {
+                    (true, b'-') => writeln!(out, "\x1b[31m{line}\x1b[0m")?,
+                    (true, b'+') => writeln!(out, "\x1b[32m{line}\x1b[0m")?,
+                    _ => writeln!(out, "{line}")?,
+                }
+            }
+            out.flush()?;
+            // Like diff(1)
+            std::process::exit(!diff.is_empty() as i32)
+        }
+        ["merge", base, ref overlays @ ..] if !overlays.is_empty() => {
+            let mut value = read(base)?;
+            for overlay in overlays {
+                value.merge(read(overlay)?, &seqs);
+            }
+            print(&mut stdout(), &value, color)
+        }
+        ["get", path] | ["get", path, _] => {
+            let steps = path::parse(path)?;
+            let value = read(args.get(2).map_or("-", String::as_str))?;
+            let mut found = Vec::new();
+            path::select(&value, &steps, &mut found);
+            path::found_any(path, &steps, found.len())?;
+            let mut out = stdout().lock();
+            for value in found {
+                match (raw, value) {
+                    (true, Value::String(s)) => out.write_all(s.as_bytes())?,
+                    _ => print(&mut out, value, color)?,
+                }
+                writeln!(out)?;
+            }
+            Ok(())
+        }
+        ["set", path, new] | ["set", path, new, _] => {
+            let steps = path::parse(path)?;
+            let new = serde_yaml::from_str(new).map_err(|e| format!("{new}: {e}"))?;
+            let file = args.get(3).map_or("-", String::as_str);
+            let mut value = read(file)?;
+            path::found_any(path, &steps, path::set(&mut value, &steps, &new))?;
+            write(file, &value, color)
+        }
+        ["delete", path] | ["delete", path, _] => {
+            let steps = path::parse(path)?;
+            let file = args.get(2).map_or("-", String::as_str);
+            let mut value = read(file)?;
+            path::found_any(path, &steps, path::delete(&mut value, &steps))?;
+            write(file, &value, color)
+        }
+        _ => usage(),
+    }
+}
+
+/// The document in file `path`, or stdin for `-`
+fn read(path: &str) -> Result<Value, Box<dyn Error>> {
+    if path == "-" {
+        return Ok(serde_yaml::from_reader(stdin())?);
+    }
+    let file = File::open(path).map_err(|e| format!("{path}: {e}"))?;
+    Ok(serde_yaml::from_reader(file).map_err(|e| format!("{path}: {e}"))?)
+}
+
+/// Write `value` back to file `path`, or stdout for `-`
+///
+/// Goes through a file next to it that is renamed over it, so that `path` stays as it was on errors.
+fn write(path: &str, value: &Value, color: bool) -> Result<(), Box<dyn Error>> {
+    if path == "-" {
+        return print(&mut stdout(), value, color);
+    }
+    let tmp = format!("{path}.cyrly-tmp");
+    let written = write_file(&tmp, path, value);
+    if written.is_err() {
+        let _ = fs::remove_file(&tmp);
+    }
+    written.map_err(|e| format!("{path}: {e}").into())
+}
+
+/// Write `value` to file `tmp`, then move it to `path` with `path`'s permissions
+fn write_file(tmp: &str, path: &str, value: &Value) -> Result<(), Box<dyn Error>> {
+    let mut file = File::create(tmp)?;
+    if let Ok(meta) = fs::metadata(path) {
+        file.set_permissions(meta.permissions())?;
+    }
+    print(&mut file, value, false)?;
+    writeln!(file)?;
+    file.sync_all()?;
+    Ok(fs::rename(tmp, path)?)
+}
+
+fn print(out: &mut impl Write, value: &Value, color: bool) -> Result<(), Box<dyn Error>> {
+    let mut out = BufWriter::new(out);
+    match color {
+        true => value.serialize(CurlySerializer::new(&mut AnsiEat::new(WriteEat(&mut out))))?,
+        false => value.serialize(CurlySerializer::new(&mut WriteEat(&mut out)))?,
+    }
+    Ok(out.flush()?)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn set_get() {
+        let file = std::env::temp_dir().join(format!("cyrly-set-get-{}.yaml", std::process::id()));
+        let file = file.to_str().unwrap();
+        fs::write(file, "k: \"2001-12-14 \\\"q\"\n").unwrap();
+        let mut value = read(file).unwrap();
+        path::set(&mut value, &path::parse("n").unwrap(), &1.into());
+        write(file, &value, false).unwrap();
+        let value = read(file).unwrap();
+        fs::remove_file(file).unwrap();
+        let mut found = Vec::new();
+        path::select(&value, &path::parse("k").unwrap(), &mut found);
+        assert_eq!(found, [&Value::from("2001-12-14 \"q")]);
+        let mut found = Vec::new();
+        path::select(&value, &path::parse("n").unwrap(), &mut found);
+        assert_eq!(found, [&Value::from(1)]);
+    }
 }
@@ -0,0 +1,331 @@
+//! Paths like `spec.containers[0].image` into a [Value]
+
+use cyrly::Value;
+
+/// One step of a path
+#[derive(Debug, Clone, PartialEq)]
+pub enum Step {
+    /// `.key` or `."some key"`, for the entry with that key, written like cyrly does
+    Key(String),
+    /// `[2]`, element of a sequence
+    Index(usize),
+    /// `*` or `[*]`, any entry or element
+    Any,
+}
+
+/// Steps of a path, `.` or an empty one for the whole document
+pub fn parse(path: &str) -> Result<Vec<Step>, String> {
+    let err = || format!("invalid path: {path}");
+    let mut steps = Vec::new();
+    let mut rest = path.strip_prefix('.').unwrap_or(path);
+    while !rest.is_empty() {
+        if let Some(index) = rest.strip_prefix('[') {
+            let (index, tail) = index.split_once(']').ok_or_else(err)?;
+            steps.push(match index {
+                "*" => Step::Any,
+                index => Step::Index(index.parse().map_err(|_| err())?),
+            });
+            rest = tail;
+        } else if let Some(quoted) = rest.strip_prefix('"') {
+            let mut key = String::new();
+            let mut chars = quoted.char_indices();
+            rest = loop {
+                match chars.next().ok_or_else(err)? {
+                    (i, '"') => break &quoted[i + 1..],
+                    (_, '\\') => key.push(chars.next().ok_or_else(err)?.1),
+                    (_, c) => key.push(c),
+                }
+            };
+            steps.push(Step::Key(key));
+        } else {
+            let end = rest.find(['.', '[']).unwrap_or(rest.len());
+            steps.push(match &rest[..end] {
+                "" => return Err(err()),
+                "*" => Step::Any,
+                key => Step::Key(key.to_owned()),
+            });
+            rest = &rest[end..];
+        }
+        if let Some(tail) = rest.strip_prefix('.') {
+            if tail.is_empty() || tail.starts_with('[') {
+                return Err(err());
+            }
+            rest = tail;
+        } else if !rest.is_empty() && !rest.starts_with('[') {
+            return Err(err());
+        }
+    }
+    Ok(steps)
+}
+
+/// Error if `count` things were found at `path`, which is none and `path` has no wildcard to match nothing
+pub fn found_any(path: &str, steps: &[Step], count: usize) -> Result<(), String> {
+    match count == 0 && !steps.contains(&Step::Any) {
+        true => Err(format!("{path}: not found")),
+        false => Ok(()),
+    }
+}
+
+/// Whether `key` is the one meant by [Step::Key]
+pub fn is_key(key: &Value, step: &str) -> bool {
+    match key {
+        Value::String(key) => key == step,
+        key => key.to_string() == step,
+    }
+}
+
+/// Everything at `steps` from `value`, looking through tags
+pub fn select<'v>(value: &'v Value, steps: &[Step], found: &mut Vec<&'v Value>) {
+    let (step, rest) = match steps.split_first() {
+        Some(split) => split,
+        None => return found.push(value),
+    };
+    match (value, step) {
+        (Value::Tagged(_, value), _) => select(value, steps, found),
+        (Value::Map(map), Step::Key(step)) => {
+            for (_, value) in map.iter().filter(|(k, _)| is_key(k, step)) {
+                select(value, rest, found);
+            }
+        }
+        (Value::Seq(seq), Step::Index(i)) => {
+            if let Some(value) = seq.get(*i) {
+                select(value, rest, found);
+            }
+        }
+        (Value::Map(map), Step::Any) => {
+            for (_, value) in map {
+                select(value, rest, found);
+            }
+        }
+        (Value::Seq(seq), Step::Any) => {
+            for value in seq {
+                select(value, rest, found);
+            }
+        }
+        _ => (),
+    }
+}
+
+/// Set everything at `steps` in `value` to `new`, adding missing entries and an element just past the end,
+/// returns how many were set
+pub fn set(value: &mut Value, steps: &[Step], new: &Value) -> usize {
+    let (step, rest) = match steps.split_first() {
+        Some(split) => split,
+        None => {
+            *value = new.clone();
+            return 1;
+        }
+    };
+    if value.is_null() && matches!(step, Step::Key(_)) {
+        *value = Value::Map(Vec::new());
+    }
+    match (value, step) {
+        (Value::Tagged(_, value), _) => set(value, steps, new),
+        (Value::Map(map), Step::Key(step)) => {
+            if !map.iter().any(|(k, _)| is_key(k, step)) {
+                map.push((Value::String(step.clone()), Value::Null));
+            }
+            map.iter_mut()
+                .filter(|(k, _)| is_key(k, step))
+                .map(|(_, value)| set(value, rest, new))
+                .sum()
+        }
+        (Value::Seq(seq), Step::Index(i)) => {
+            if *i == seq.len() {
+                seq.push(Value::Null);
+            }
+            seq.get_mut(*i).map_or(0, |value| set(value, rest, new))
+        }
+        (Value::Map(map), Step::Any) => map.iter_mut().map(|(_, v)| set(v, rest, new)).sum(),
+        (Value::Seq(seq), Step::Any) => seq.iter_mut().map(|v| set(v, rest, new)).sum(),
+        _ => 0,
+    }
+}
+
+/// Remove everything at `steps` from `value`, returns how many were removed
+pub fn delete(value: &mut Value, steps: &[Step]) -> usize {
+    let (step, rest) = match steps.split_first() {
+        Some(split) => split,
+        None => return 0,
+    };
+    match (value, step) {
+        (Value::Tagged(_, value), _) => delete(value, steps),
+        (Value::Map(map), Step::Key(step)) if rest.is_empty() => {
+            let len = map.len();
+            map.retain(|(k, _)| !is_key(k, step));
+            len - map.len()
+        }
+        (Value::Seq(seq), Step::Index(i)) if rest.is_empty() => match *i < seq.len() {
+            true => {
+                seq.remove(*i);
+                1
+            }
+            false => 0,
+        },
+        (Value::Map(map), Step::Any) if rest.is_empty() => core::mem::take(map).len(),
+        (Value::Seq(seq), Step::Any) if rest.is_empty() => core::mem::take(seq).len(),
+        (Value::Map(map), Step::Key(step)) => map
+            .iter_mut()
+            .filter(|(k, _)| is_key(k, step))
+            .map(|(_, value)| delete(value, rest))
+            .sum(),
+        (Value::Seq(seq), Step::Index(i)) => seq.get_mut(*i).map_or(0, |v| delete(v, rest)),
+        (Value::Map(map), Step::Any) => map.iter_mut().map(|(_, v)| delete(v, rest)).sum(),
+        (Value::Seq(seq), Step::Any) => seq.iter_mut().map(|v| delete(v, rest)).sum(),
+        _ => 0,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use cyrly::cyrly;
+
+    fn key(key: &str) -> Step {
+        Step::Key(key.to_owned())
+    }
+
+    #[test]
+    fn parse_paths() {
+        assert_eq!(parse(""), Ok(vec![]));
+        assert_eq!(parse("."), Ok(vec![]));
+        assert_eq!(
+            parse("spec.containers[0].image"),
+            Ok(vec![
+                key("spec"),
+                key("containers"),
+                Step::Index(0),
+                key("image")
+            ])
+        );
+        assert_eq!(
+            parse(".a[1][2]"),
+            Ok(vec![key("a"), Step::Index(1), Step::Index(2)])
+        );
+        assert_eq!(
+            parse(r#"a."b.c"."\"q\" \\"[0]"#),
+            Ok(vec![key("a"), key("b.c"), key(r#""q" \"#), Step::Index(0)])
+        );
+        assert_eq!(parse("*.b[*]"), Ok(vec![Step::Any, key("b"), Step::Any]));
+        assert_eq!(parse("1.true"), Ok(vec![key("1"), key("true")]));
+        for invalid in [
+            "a..b", "a.", "a.[0]", "..", "a[", "a[x]", "a[-1]", "[0]b", r#""a"#, r#""a"b"#,
+            r#"a."\"#,
+        ] {
+            assert_eq!(parse(invalid), Err(format!("invalid path: {invalid}")));
+        }
+    }
+
+    #[test]
+    fn select_paths() {
+        let doc = cyrly!({
+            spec: {
+                containers: [{ name: "a", image: "x" }, { name: "b", image: "y" }],
+                env: !Prod { replicas: 3 },
+            },
+            1: "one",
+            true: "yes",
+            null: "nothing",
+        });
+        let get = |path| {
+            let steps = parse(path).unwrap();
+            let mut found = Vec::new();
+            select(&doc, &steps, &mut found);
+            let found: Vec<_> = found.into_iter().cloned().collect();
+            found_any(path, &steps, found.len()).map(|()| found)
+        };
+        assert_eq!(get("spec.containers[1].name"), Ok(vec!["b".into()]));
+        assert_eq!(
+            get("spec.containers[*].image"),
+            Ok(vec!["x".into(), "y".into()])
+        );
+        assert_eq!(get("spec.env.replicas"), Ok(vec![3.into()]));
+        assert_eq!(get("spec.*").unwrap().len(), 2);
+        assert_eq!(get("."), Ok(vec![doc.clone()]));
+        // Keys that aren't strings, by how cyrly writes them
+        assert_eq!(get("1"), Ok(vec!["one".into()]));
+        assert_eq!(get("true"), Ok(vec!["yes".into()]));
+        assert_eq!(get("null"), Ok(vec!["nothing".into()]));
+        // Not finding anything is only fine with a wildcard
+        assert_eq!(
+            get("spec.containers[2]"),
+            Err("spec.containers[2]: not found".into())
+        );
+        assert_eq!(get("spec.nope"), Err("spec.nope: not found".into()));
+        assert_eq!(get("spec.containers[*].nope"), Ok(vec![]));
+        assert_eq!(get("*.containers[9]"), Ok(vec![]));
+    }
+
+    #[test]
+    fn set_paths() {
+        let set = |mut doc: Value, path, new: Value| {
+            let count = set(&mut doc, &parse(path).unwrap(), &new);
+            (count, doc)
+        };
+        assert_eq!(set(cyrly!({ a: 1 }), "a", 2.into()), (1, cyrly!({ a: 2 })));
+        assert_eq!(set(cyrly!({ a: 1 }), ".", 2.into()), (1, cyrly!(2)));
+        // Maps are made on null and for missing keys
+        assert_eq!(
+            set(cyrly!(null), "a.b", 1.into()),
+            (1, cyrly!({ a: { b: 1 } }))
+        );
+        assert_eq!(
+            set(cyrly!({ a: null }), "a.b.c", 1.into()),
+            (1, cyrly!({ a: { b: { c: 1 } } }))
+        );
+        assert_eq!(
+            set(cyrly!({ a: 1 }), "b", 2.into()),
+            (1, cyrly!({ a: 1, b: 2 }))
+        );
+        assert_eq!(
+            set(cyrly!({ a: !T {} }), "a.b", 1.into()),
+            (1, cyrly!({ a: !T { b: 1 } }))
+        );
+        // Sequences, on the other hand, grow only at their end
+        assert_eq!(set(cyrly!([1]), "[0]", 2.into()), (1, cyrly!([2])));
+        assert_eq!(set(cyrly!([1]), "[1]", 2.into()), (1, cyrly!([1, 2])));
+        assert_eq!(set(cyrly!([1]), "[2]", 2.into()), (0, cyrly!([1])));
+        assert_eq!(set(cyrly!([]), "[0].a", 1.into()), (1, cyrly!([{ a: 1 }])));
+        assert_eq!(set(cyrly!(null), "[0]", 1.into()), (0, cyrly!(null)));
+        assert_eq!(
+            set(cyrly!({ a: 1 }), "a.b", 2.into()),
+            (0, cyrly!({ a: 1 }))
+        );
+        assert_eq!(
+            set(cyrly!([{ a: 1 }, { b: 2 }]), "[*].a", 3.into()),
+            (2, cyrly!([{ a: 3 }, { b: 2, a: 3 }]))
+        );
+        assert_eq!(set(cyrly!([]), "[*].a", 3.into()), (0, cyrly!([])));
+    }
+
+    #[test]
+    fn delete_paths() {
+        let delete = |mut doc: Value, path| {
+            let count = delete(&mut doc, &parse(path).unwrap());
+            (count, doc)
+        };
+        assert_eq!(delete(cyrly!({ a: 1, b: 2 }), "a"), (1, cyrly!({ b: 2 })));
+        assert_eq!(delete(cyrly!({ a: 1 }), "b"), (0, cyrly!({ a: 1 })));
+        assert_eq!(delete(cyrly!({ a: 1 }), "."), (0, cyrly!({ a: 1 })));
+        assert_eq!(delete(cyrly!([1, 2, 3]), "[1]"), (1, cyrly!([1, 3])));
+        assert_eq!(delete(cyrly!([1, 2, 3]), "[3]"), (0, cyrly!([1, 2, 3])));
+        assert_eq!(
+            delete(cyrly!({ a: !T { b: 1 } }), "a.b"),
+            (1, cyrly!({ a: !T {} }))
+        );
+        // A trailing wildcard empties the container rather than removing it
+        assert_eq!(
+            delete(cyrly!({ a: [1, 2], b: 3 }), "a[*]"),
+            (2, cyrly!({ a: [], b: 3 }))
+        );
+        assert_eq!(
+            delete(cyrly!({ a: { b: 1, c: 2 } }), "a.*"),
+            (2, cyrly!({ a: {} }))
+        );
+        assert_eq!(delete(cyrly!({ a: 1 }), "*"), (1, cyrly!({})));
+        assert_eq!(
+            delete(cyrly!([{ a: 1, b: 2 }, { b: 3 }]), "[*].a"),
+            (1, cyrly!([{ b: 2 }, { b: 3 }]))
+        );
+    }
+}
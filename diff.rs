@@ -0,0 +1,280 @@
+//! Structural diff of two documents, see [diff]
+
+use super::*;
+use alloc::{format, vec};
+
+/// Differences between `a` and `b`, in cyrly layout
+///
+/// Lines of entries and elements only in `a` start with `-`, those only in `b` with `+`,
+/// and the maps and sequences around them are kept to show where they are.
+/// Unchanged entries and elements stay for context, but maps and sequences in them are collapsed to `{ … }` and `[ … ]`.
+/// Empty if there are no differences.
+pub fn diff<A: ?Sized + Serialize, B: ?Sized + Serialize>(a: &A, b: &B) -> Result<String, Error> {
+    let (a, b) = (to_value(a)?, to_value(b)?);
+    let mut diff = Diff(String::new());
+    if !identical(&a, &b) {
+        diff.changed(0, "", &a, &b, false)?;
+    }
+    Ok(diff.0)
+}
+
+/// Sequences that differ in more than this many elements squared get matched up by index only
+const MAX_LCS: usize = 1 << 20;
+
+struct Diff(String);
+
+impl Diff {
+    /// `head` and `value`, with `marker` on every line, as the value of something on line `level`
+    fn value(
+        &mut self,
+        marker: char,
+        level: usize,
+        head: &str,
+        value: &Value,
+        comma: bool,
+    ) -> Result<(), Error> {
+        let mut text = String::new();
+        let mut ser = CurlySerializer::new(&mut text);
+        ser.level = level;
+        value.serialize(ser)?;
+        // Lines after the first are indented already
+        for (i, line) in text.split('\n').enumerate() {
+            if i > 0 {
+                self.0.push('\n');
+            }
+            self.0.push(marker);
+            self.0.push(' ');
+            if i == 0 {
+                self.indent(level);
+                self.0.push_str(head);
+            }
+            self.0.push_str(line);
+        }
+        self.end(comma);
+        Ok(())
+    }
+
+    /// `value` unchanged, with maps and sequences in it collapsed
+    fn same(&mut self, level: usize, head: &str, value: &Value, comma: bool) -> Result<(), Error> {
+        let collapsed = match value {
+            Value::Map(map) if !map.is_empty() => "{ … }",
+            Value::Seq(seq) if !seq.is_empty() => "[ … ]",
+            Value::Tagged(tag, value) => {
+                return self.same(level, &format!("{head}!{tag} "), value, comma)
+            }
+            _ => return self.value(' ', level, head, value, comma),
+        };
+        self.0.push_str("  ");
+        self.indent(level);
+        self.0.push_str(head);
+        self.0.push_str(collapsed);
+        self.end(comma);
+        Ok(())
+    }
+
+    /// `a` changed to `b`, going into maps and sequences that are both
+    fn changed(
+        &mut self,
+        level: usize,
+        head: &str,
+        a: &Value,
+        b: &Value,
+        comma: bool,
+    ) -> Result<(), Error> {
+        match (a, b) {
+            (Value::Map(a), Value::Map(b)) => {
+                self.open(level, head, '{');
+                self.entries(level + 1, a, b)?;
+                self.close(level, '}', comma);
+            }
+            (Value::Seq(a), Value::Seq(b)) => {
+                self.open(level, head, '[');
+                self.elements(level + 1, a, b)?;
+                self.close(level, ']', comma);
+            }
+            (Value::Tagged(t, a), Value::Tagged(u, b)) if t == u => {
+                self.changed(level, &format!("{head}!{t} "), a, b, comma)?;
+            }
+            _ => {
+                self.value('-', level, head, a, comma)?;
+                self.value('+', level, head, b, comma)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Entries by key, in the order of `a`, then those only in `b`
+    fn entries(
+        &mut self,
+        level: usize,
+        a: &[(Value, Value)],
+        b: &[(Value, Value)],
+    ) -> Result<(), Error> {
+        for (key, v) in a {
+            let head = key_head(key)?;
+            match b.iter().find(|(k, _)| identical(k, key)) {
+                Some((_, w)) if identical(v, w) => self.same(level, &head, v, true)?,
+                Some((_, w)) => self.changed(level, &head, v, w, true)?,
+                None => self.value('-', level, &head, v, true)?,
+            }
+        }
+        for (key, w) in b {
+            if !a.iter().any(|(k, _)| identical(k, key)) {
+                self.value('+', level, &key_head(key)?, w, true)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Elements matched up by longest common subsequence,
+    /// removed and added ones in between are compared if they are [similar]
+    fn elements(&mut self, level: usize, a: &[Value], b: &[Value]) -> Result<(), Error> {
+        let (mut i, mut j) = (0, 0);
+        for (v, w) in align(a, b, identical) {
+            self.changed_run(level, &a[i..v], &b[j..w])?;
+            self.same(level, "", &a[v], true)?;
+            (i, j) = (v + 1, w + 1);
+        }
+        self.changed_run(level, &a[i..], &b[j..])
+    }
+
+    /// Elements between two matches
+    fn changed_run(&mut self, level: usize, a: &[Value], b: &[Value]) -> Result<(), Error> {
+        let (mut i, mut j) = (0, 0);
+        let mut pairs = align(a, b, similar).into_iter();
+        loop {
+            let (v, w) = pairs.next().unwrap_or((a.len(), b.len()));
+            for v in &a[i..v] {
+                self.value('-', level, "", v, true)?;
+            }
+            for w in &b[j..w] {
+                self.value('+', level, "", w, true)?;
+            }
+            match (a.get(v), b.get(w)) {
+                (Some(v), Some(w)) => self.changed(level, "", v, w, true)?,
+                _ => return Ok(()),
+            }
+            (i, j) = (v + 1, w + 1);
+        }
+    }
+
+    fn open(&mut self, level: usize, head: &str, bracket: char) {
+        self.0.push_str("  ");
+        self.indent(level);
+        self.0.push_str(head);
+        self.0.push(bracket);
+        self.0.push('\n');
+    }
+
+    fn close(&mut self, level: usize, bracket: char, comma: bool) {
+        self.0.push_str("  ");
+        self.indent(level);
+        self.0.push(bracket);
+        self.end(comma);
+    }
+
+    fn indent(&mut self, level: usize) {
+        for _ in 0..level {
+            self.0.push_str("  ");
+        }
+    }
+
+    fn end(&mut self, comma: bool) {
+        if comma {
+            self.0.push(',');
+        }
+        self.0.push('\n');
+    }
+}
+
+/// `key: `, with the key written like [CurlySerializer] does
+fn key_head(key: &Value) -> Result<String, Error> {
+    let mut head = String::new();
+    let mut ser = CurlySerializer::new(&mut head).oneline();
+    ser.key = true;
+    key.serialize(ser)?;
+    head.push_str(": ");
+    Ok(head)
+}
+
+/// Indices of matching elements of `a` and `b`, for a longest common subsequence by `eq`, in order
+fn align(a: &[Value], b: &[Value], eq: impl Fn(&Value, &Value) -> bool) -> Vec<(usize, usize)> {
+    let prefix = a.iter().zip(b).take_while(|(v, w)| eq(v, w)).count();
+    let suffix = a[prefix..]
+        .iter()
+        .rev()
+        .zip(b[prefix..].iter().rev())
+        .take_while(|(v, w)| eq(v, w))
+        .count();
+    let (a_mid, b_mid) = (&a[prefix..a.len() - suffix], &b[prefix..b.len() - suffix]);
+    let (n, m) = (a_mid.len(), b_mid.len());
+    let mut pairs: Vec<_> = (0..prefix).map(|i| (i, i)).collect();
+    if n.saturating_mul(m) > MAX_LCS {
+        let matching = (0..n.min(m)).filter(|&k| eq(&a_mid[k], &b_mid[k]));
+        pairs.extend(matching.map(|k| (prefix + k, prefix + k)));
+    } else {
+        // lcs[i * (m + 1) + j]: length of the longest common subsequence of a_mid[i..] and b_mid[j..]
+        let mut lcs = vec![0usize; (n + 1) * (m + 1)];
+        for i in (0..n).rev() {
+            for j in (0..m).rev() {
+                lcs[i * (m + 1) + j] = match eq(&a_mid[i], &b_mid[j]) {
+                    true => lcs[(i + 1) * (m + 1) + j + 1] + 1,
+                    false => lcs[(i + 1) * (m + 1) + j].max(lcs[i * (m + 1) + j + 1]),
+                };
+            }
+        }
+        let (mut i, mut j) = (0, 0);
+        while i < n && j < m {
+            if eq(&a_mid[i], &b_mid[j]) {
+                pairs.push((prefix + i, prefix + j));
+                i += 1;
+                j += 1;
+            } else if lcs[(i + 1) * (m + 1) + j] >= lcs[i * (m + 1) + j + 1] {
+                i += 1;
+            } else {
+                j += 1;
+            }
+        }
+    }
+    let (a_end, b_end) = (a.len() - suffix, b.len() - suffix);
+    pairs.extend((0..suffix).map(|k| (a_end + k, b_end + k)));
+    pairs
+}
+
+/// Whether `a` and `b` are maps or sequences with at least half of their entries or elements in common,
+/// so it's worth showing how one changed into the other
+fn similar(a: &Value, b: &Value) -> bool {
+    let (common, len) = match (a, b) {
+        (Value::Map(a), Value::Map(b)) => (
+            a.iter()
+                .filter(|(k, v)| b.iter().any(|(l, w)| identical(k, l) && identical(v, w)))
+                .count(),
+            a.len().max(b.len()),
+        ),
+        (Value::Seq(a), Value::Seq(b)) => (
+            a.iter()
+                .filter(|v| b.iter().any(|w| identical(v, w)))
+                .count(),
+            a.len().max(b.len()),
+        ),
+        (Value::Tagged(t, a), Value::Tagged(u, b)) => return t == u && similar(a, b),
+        _ => return false,
+    };
+    common * 2 >= len
+}
+
+/// `a == b`, except that floats are compared by their bits, so that NaN is the same as itself
+fn identical(a: &Value, b: &Value) -> bool {
+    match (a, b) {
+        (Value::Float(a), Value::Float(b)) => a.to_bits() == b.to_bits(),
+        (Value::Seq(a), Value::Seq(b)) => {
+            a.len() == b.len() && a.iter().zip(b).all(|(v, w)| identical(v, w))
+        }
+        (Value::Map(a), Value::Map(b)) => {
+            a.len() == b.len()
+                && (a.iter().zip(b)).all(|((k, v), (l, w))| identical(k, l) && identical(v, w))
+        }
+        (Value::Tagged(t, a), Value::Tagged(u, b)) => t == u && identical(a, b),
+        _ => a == b,
+    }
+}
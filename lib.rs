@@ -2,16 +2,27 @@
 #![forbid(unsafe_code)]
 #![no_std]
 
+#[cfg(feature = "alloc")]
+mod diff;
+mod resolve;
 #[cfg(test)]
 mod test;
-
+#[cfg(feature = "alloc")]
+mod value;
+#[cfg(feature = "alloc")]
+pub use diff::diff;
+#[cfg(feature = "alloc")]
+pub use value::{__private, to_value, SeqMerge, Value};
+
+#[cfg(feature = "alloc")]
 extern crate alloc;
 #[cfg(feature = "std")]
 extern crate std;
 
+#[cfg(feature = "alloc")]
 use alloc::{
-    format,
     string::{String, ToString},
+    vec::Vec,
 };
 use serde::{
     ser::{
@@ -21,39 +32,167 @@ use serde::{
     Serialize, Serializer,
 };
 
+#[cfg(feature = "alloc")]
 /// Serialize the given data structure as a string
-pub fn to_string<T: Serialize + ?Sized>(value: &T) -> Result<String, core::fmt::Error> {
+pub fn to_string<T: Serialize + ?Sized>(value: &T) -> Result<String, Error> {
+    let mut out = String::new();
+    value.serialize(CurlySerializer::new(&mut out))?;
+    Ok(out)
+}
+
+#[cfg(feature = "alloc")]
+/// Serialize the given data structure as a string of at most `max_len` bytes
+///
+/// Longer output is cut short and marked with `…`, but stays valid YAML.
+/// Space for closing brackets is kept free while writing,
+/// so output that only just fits may get cut, too.
+/// `max_len` should be at least a few dozen bytes, below that the output may be only `…`.
+/// It must be at least 3 bytes, the length of `…`, or serialization fails.
+pub fn to_string_truncated<T: Serialize + ?Sized>(
+    value: &T,
+    max_len: usize,
+) -> Result<String, Error> {
     let mut out = String::new();
+    serialize_truncated(value, &mut out, true, max_len)?;
+    Ok(out)
+}
+
+#[cfg(feature = "alloc")]
+/// Serialize the given data structure as a string, with where each element and entry ended up in it
+///
+/// The first [Span] is for the whole output, the others follow in the order they start.
+pub fn to_string_with_spans<T: Serialize + ?Sized>(
+    value: &T,
+) -> Result<(String, Vec<Span>), Error> {
+    let mut glut = SpanEat::new(String::new());
+    value.serialize(CurlySerializer::new(&mut glut))?;
+    Ok(glut.finish())
+}
+
+/// Serialize with a [Limit] that truncates
+fn serialize_truncated<T: Serialize + ?Sized, E: Eat>(
+    value: &T,
+    glut: &mut E,
+    multiline: bool,
+    max_len: usize,
+) -> Result<(), E::Error> {
+    let mut limit = Limit::truncate(max_len);
+    let mut ser = CurlySerializer::new(glut);
+    ser.multiline = multiline;
+    ser.limit = Some(&mut limit);
+    match value.serialize(ser) {
+        Err(e) if !limit.truncated() => Err(e),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(feature = "alloc")]
+/// Serialize the given data structure as UTF-8 bytes
+pub fn to_vec<T: Serialize + ?Sized>(value: &T) -> Result<Vec<u8>, Error> {
+    let mut out = Vec::new();
     value.serialize(CurlySerializer::new(&mut out))?;
     Ok(out)
 }
 
+/// Serialize the given data structure into `buf`, returns the length of the output
+///
+/// This works without the alloc feature.
+pub fn to_slice<T: Serialize + ?Sized>(buf: &mut [u8], value: &T) -> Result<usize, SliceError> {
+    let len = buf.len();
+    let mut rest = buf;
+    value.serialize(CurlySerializer::new(&mut rest))?;
+    Ok(len - rest.len())
+}
+
 #[cfg(feature = "std")]
 /// Serialize the given data structure into the stream
 ///
-/// Note: the output will be written in small chunks, often single bytes.
-/// This can lead to terrible performance when the write is directly flushed to the operating system.
-/// If in doubt, use a [BufWriter][std::io::BufWriter].
-pub fn to_writer<W, T>(writer: W, value: &T) -> Result<(), std::io::Error>
+/// The output is buffered internally and flushed at the end,
+/// there is no need to wrap `writer` in a [BufWriter][std::io::BufWriter].
+pub fn to_writer<W, T>(writer: W, value: &T) -> Result<(), Error>
 where
     W: std::io::Write,
     T: ?Sized + Serialize,
 {
-    value
-        .serialize(CurlySerializer::new(&mut write::WriteEat(writer)))
-        .map_err(|write::WriteEatError(e)| e)
+    to_writer_with_capacity(writer, 8 * 1024, value)
+}
+
+#[cfg(feature = "std")]
+/// Like [to_writer], with `capacity` bytes of buffer
+///
+/// With a capacity of 0, the output will be written in small chunks, often single bytes.
+pub fn to_writer_with_capacity<W, T>(writer: W, capacity: usize, value: &T) -> Result<(), Error>
+where
+    W: std::io::Write,
+    T: ?Sized + Serialize,
+{
+    use std::io::Write;
+    let mut glut = write::WriteEat(std::io::BufWriter::with_capacity(capacity, writer));
+    value.serialize(CurlySerializer::new(&mut glut))?;
+    Ok(glut.0.flush()?)
 }
 
 /// Main serializer implementation
 ///
 /// Note that this serializer produces YAML tags for enums, e.g. `enum Foo { Bar(i32) }` will result in `!Bar 42`.
 /// See [serde_yaml::with](https://docs.rs/serde_yaml/latest/serde_yaml/with/index.html) for configuration options.
+/// (With [Schema::JsonCompatible] or [Schema::Json5], that becomes `{"Bar": 42}`, like serde_json would do it.)
 pub struct CurlySerializer<'a, E> {
     /// Use more than one line (defaults to true in `new`)
     pub multiline: bool,
+    /// Which parsers the output has to be understood by (defaults to [Schema::Yaml11] in `new`)
+    pub schema: Schema,
+    /// Refuse to produce anything picky parsers might choke on:
+    /// keys that aren't strings or are longer than 1024 bytes as written, tags, and non-finite floats (defaults to false in `new`)
+    pub strict: bool,
+    /// Cap on the output length, see [Limit] (defaults to none in `new`)
+    pub limit: Option<&'a mut Limit>,
+    /// Sequences and maps nested deeper than this only show how many elements they have,
+    /// e.g. `[ …3 more ]` (defaults to none in `new`)
+    pub max_depth: Option<usize>,
+    /// Sequences and maps show at most this many elements, followed by e.g. `…47 more` (defaults to none in `new`)
+    ///
+    /// Both are meant for previews: the output still parses, but keys that are maps or sequences
+    /// may end up looking the same, which YAML parsers refuse as duplicate keys.
+    pub max_elements: Option<usize>,
     level: usize,
+    key: bool,
     glut: &'a mut E,
     max_output: Option<&'a mut usize>,
+    widths: Option<&'a mut KeyWidths>,
+}
+
+/// Target schema, deciding which strings need quoting, or whether to produce YAML at all
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Schema {
+    /// Quote anything a YAML 1.1 or YAML 1.2 parser might read as something other than a string
+    #[default]
+    Yaml11,
+    /// Only quote what the YAML 1.2 core schema resolves, e.g. `yes` or `2001-12-14` stay plain
+    Yaml12Core,
+    /// Produce JSON (which is also YAML 1.2):
+    /// All keys and strings are double-quoted, no trailing commas, enums are externally tagged maps,
+    /// and non-finite floats become `null`.
+    /// Keys that aren't strings, numbers or bools are refused.
+    JsonCompatible,
+    /// Produce [JSON5](https://json5.org/), which is *not* YAML:
+    /// Like [Schema::JsonCompatible], but keys that are identifiers stay unquoted,
+    /// trailing commas are kept, and non-finite floats are written as `NaN` and `Infinity`.
+    Json5,
+}
+
+impl Schema {
+    /// Strings that would not be read back as strings if written plain, JSON has no plain strings
+    fn is_special_str(self, v: &str) -> bool {
+        match self {
+            Schema::Yaml11 => resolve::yaml11(v) || resolve::yaml12(v),
+            _ => resolve::yaml12(v),
+        }
+    }
+
+    fn is_json(self) -> bool {
+        matches!(self, Schema::JsonCompatible | Schema::Json5)
+    }
 }
 
 /// Helper trait for data output from serializer
@@ -64,15 +203,746 @@ pub trait Eat {
     type Error: ser::Error;
     /// Output some tokens
     fn eat(&mut self, data: &str) -> Result<(), Self::Error>;
+    /// Error from the serializer itself, e.g. when something can't be written in the [Schema]
+    fn error(kind: ErrorKind, msg: &'static str) -> Self::Error
+    where
+        Self: Sized,
+    {
+        let _ = kind;
+        ser::Error::custom(msg)
+    }
+    /// Called with each element or entry an error passes on its way out, innermost first
+    fn locate(error: Self::Error, at: Segment<'_>) -> Self::Error
+    where
+        Self: Sized,
+    {
+        let _ = at;
+        error
+    }
+    /// Whether [Eat::event] does more than [Event::write_to]
+    ///
+    /// If not, [Event::Begin] and [Event::End] are left out, and parts of the output that fit on one line may be passed as one [Event::Text], without telling the tokens apart.
+    fn wants_events(&self) -> bool {
+        false
+    }
+    /// Output one piece of the output, by default its text, see [Event::write_to]
+    fn event(&mut self, event: Event<'_>) -> Result<(), Self::Error> {
+        event.write_to(self)
+    }
+}
+
+/// Piece of the output passed to [Eat::event]
+///
+/// For a scalar or tag, [Event::Begin] and [Event::End] go around the [Event::Text]s that make it up.
+/// The `…` markers of [Limit] and [CurlySerializer::max_elements] are [Event::Text]s outside of them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum Event<'a> {
+    /// Text of a scalar or tag, or a marker
+    Text(&'a str),
+    /// `[`
+    SeqStart,
+    /// `]`
+    SeqEnd,
+    /// `{`
+    MapStart,
+    /// `}`
+    MapEnd,
+    /// `,`
+    Comma,
+    /// `: ` between key and value
+    Colon,
+    /// `? ` before a key that doesn't fit on one line
+    ComplexKey,
+    /// Line break, followed by two spaces of indentation per level
+    Newline {
+        /// Indentation level
+        level: usize,
+    },
+    /// A space where multiline output would have a [Event::Newline], or after a tag
+    Space,
+    /// Start of a token, not [Token::Punct]
+    Begin {
+        /// Kind of the token
+        token: Token,
+        /// How it's written, [ScalarStyle::Plain] for tags
+        style: ScalarStyle,
+    },
+    /// End of the token of the last [Event::Begin], tokens don't nest
+    End(Token),
+    /// Start of the value of an element or entry, not inside keys
+    Enter(Segment<'a>),
+    /// End of the value of the last [Event::Enter] that hasn't ended yet
+    Leave,
+}
+
+impl Event<'_> {
+    /// The text of this event, e.g. `": "` for [Event::Colon], nothing for [Event::Begin] and [Event::End]
+    #[inline(always)]
+    pub fn write_to<E: Eat + ?Sized>(&self, eat: &mut E) -> Result<(), E::Error> {
+        match *self {
+            Event::Text(v) => eat.eat(v),
+            Event::Newline { level } => write_newline(eat, level),
+            Event::Space => eat.eat(" "),
+            Event::Begin { .. } | Event::End(_) | Event::Enter(_) | Event::Leave => Ok(()),
+            _ => eat.eat(self.punct().expect("punctuation")),
+        }
+    }
+
+    /// Length of the text written by [Event::write_to]
+    #[inline(always)]
+    pub fn len(&self) -> usize {
+        match *self {
+            Event::Text(v) => v.len(),
+            Event::Newline { level } => 1 + 2 * level,
+            Event::Space => 1,
+            Event::Begin { .. } | Event::End(_) | Event::Enter(_) | Event::Leave => 0,
+            _ => self.punct().expect("punctuation").len(),
+        }
+    }
+
+    /// Whether [Event::write_to] writes nothing
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Text of the events that are [Token::Punct]
+    #[inline(always)]
+    pub fn punct(&self) -> Option<&'static str> {
+        match self {
+            Event::SeqStart => Some("["),
+            Event::SeqEnd => Some("]"),
+            Event::MapStart => Some("{"),
+            Event::MapEnd => Some("}"),
+            Event::Comma => Some(","),
+            Event::Colon => Some(": "),
+            Event::ComplexKey => Some("? "),
+            _ => None,
+        }
+    }
 }
 
+#[inline(never)]
+fn write_newline<E: Eat + ?Sized>(eat: &mut E, level: usize) -> Result<(), E::Error> {
+    const NEWLINE_INDENT: &str = concat!(
+        "\n",
+        "                                                                ",
+        "                                                                "
+    );
+    let mut width = 2 * level;
+    let mut chunk = &NEWLINE_INDENT[..1 + width.min(NEWLINE_INDENT.len() - 1)];
+    while !chunk.is_empty() {
+        eat.eat(chunk)?;
+        width -= chunk.trim_start_matches('\n').len();
+        chunk = &NEWLINE_INDENT[1..1 + width.min(NEWLINE_INDENT.len() - 1)];
+    }
+    Ok(())
+}
+
+/// How a scalar is written, see [Event::Begin]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum ScalarStyle {
+    /// Without quotes
+    Plain,
+    /// Double-quoted, on one line
+    Quoted,
+    /// Double-quoted, spread over several lines with escaped line breaks
+    Multiline,
+}
+
+/// Kind of a piece of the output, for syntax highlighting, see [Event::Begin]
+///
+/// Whitespace, and the `…` markers of [Limit] and [CurlySerializer::max_elements] are not part of any token.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum Token {
+    /// Scalar used as a map key, with its quotes
+    Key,
+    /// String, with its quotes
+    Str,
+    /// Number
+    Num,
+    /// `true`, `false`, or `null`
+    Literal,
+    /// YAML tag of an enum variant, e.g. `!Bar`
+    Tag,
+    /// Brackets, `,`, `:`, and `?`, which have their own [Event]s
+    Punct,
+}
+
+impl Token {
+    /// Lowercase name, e.g. `"key"`, also used as the class by [HtmlEat]
+    pub fn name(self) -> &'static str {
+        match self {
+            Token::Key => "key",
+            Token::Str => "str",
+            Token::Num => "num",
+            Token::Literal => "literal",
+            Token::Tag => "tag",
+            Token::Punct => "punct",
+        }
+    }
+}
+
+#[cfg(feature = "alloc")]
 impl Eat for String {
-    type Error = core::fmt::Error;
+    type Error = Error;
 
     fn eat(&mut self, data: &str) -> Result<(), Self::Error> {
         self.push_str(data);
         Ok(())
     }
+
+    fn error(kind: ErrorKind, msg: &'static str) -> Self::Error {
+        Error::new(kind, msg)
+    }
+
+    fn locate(error: Self::Error, at: Segment<'_>) -> Self::Error {
+        error.locate(at)
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl Eat for Vec<u8> {
+    type Error = Error;
+
+    fn error(kind: ErrorKind, msg: &'static str) -> Self::Error {
+        Error::new(kind, msg)
+    }
+
+    fn locate(error: Self::Error, at: Segment<'_>) -> Self::Error {
+        error.locate(at)
+    }
+
+    fn eat(&mut self, data: &str) -> Result<(), Self::Error> {
+        self.extend_from_slice(data.as_bytes());
+        Ok(())
+    }
+}
+
+/// Fills the slice from the front, and moves on to the unused rest, like `std::io::Write` does
+impl Eat for &mut [u8] {
+    type Error = SliceError;
+
+    fn eat(&mut self, data: &str) -> Result<(), Self::Error> {
+        if data.len() > self.len() {
+            return Err(SliceError::BufferFull);
+        }
+        let (head, tail) = core::mem::take(self).split_at_mut(data.len());
+        head.copy_from_slice(data.as_bytes());
+        *self = tail;
+        Ok(())
+    }
+}
+
+/// Error when serializing into a `&mut [u8]`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SliceError {
+    /// The output doesn't fit, the slice holds as much of it as fit, cut at a token boundary
+    BufferFull,
+    /// Any other error, e.g. one of the refusals of [CurlySerializer::strict]
+    Other,
+}
+impl core::fmt::Display for SliceError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            SliceError::BufferFull => f.write_str("buffer full"),
+            SliceError::Other => f.write_str("error while serializing"),
+        }
+    }
+}
+impl ser::StdError for SliceError {}
+impl ser::Error for SliceError {
+    fn custom<T: core::fmt::Display>(_msg: T) -> Self {
+        SliceError::Other
+    }
+}
+
+/// What went wrong, see [Error::kind]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ErrorKind {
+    /// Writing the output failed
+    Io,
+    /// Raised by a [Serialize] implementation
+    Custom,
+    /// The data can't be written in the [Schema], or is refused by [CurlySerializer::strict]
+    Unsupported,
+    /// The output got longer than the [Limit], or was truncated
+    Limit,
+}
+
+/// Element or entry an error happened in, see [Eat::locate], or one that starts, see [Event::Enter]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Segment<'a> {
+    /// Sequence element, counting from 0
+    Index(usize),
+    /// Map entry or struct field, with the key as written in the output, on one line
+    Key(&'a str),
+}
+
+#[cfg(feature = "alloc")]
+/// Error of [to_string] and friends, with where in the data it happened
+#[derive(Debug)]
+pub struct Error(alloc::boxed::Box<ErrorInner>);
+
+#[cfg(feature = "alloc")]
+/// Boxed, to keep results small on the happy path
+#[derive(Debug)]
+struct ErrorInner {
+    kind: ErrorKind,
+    message: String,
+    path: String,
+    #[cfg(feature = "std")]
+    io: Option<std::io::Error>,
+}
+
+#[cfg(feature = "alloc")]
+impl Error {
+    fn new(kind: ErrorKind, message: impl ToString) -> Self {
+        Error(alloc::boxed::Box::new(ErrorInner {
+            kind,
+            message: message.to_string(),
+            path: String::new(),
+            #[cfg(feature = "std")]
+            io: None,
+        }))
+    }
+
+    fn locate(mut self, at: Segment<'_>) -> Self {
+        let inner = core::mem::take(&mut self.0.path);
+        let sep = match inner.starts_with('[') || inner.is_empty() {
+            true => "",
+            false => ".",
+        };
+        self.0.path = match at {
+            Segment::Index(i) => alloc::format!("[{i}]{sep}{inner}"),
+            Segment::Key(key) => alloc::format!("{key}{sep}{inner}"),
+        };
+        self
+    }
+
+    /// What went wrong
+    pub fn kind(&self) -> ErrorKind {
+        self.0.kind
+    }
+
+    /// Description of the error, without the path
+    pub fn message(&self) -> &str {
+        &self.0.message
+    }
+
+    /// Keys and indices leading to where the error happened, e.g. `spec.containers[2].env`, empty at the top level
+    pub fn path(&self) -> &str {
+        &self.0.path
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl core::fmt::Display for Error {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str(&self.0.message)?;
+        if !self.0.path.is_empty() {
+            write!(f, " at {}", self.0.path)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl ser::StdError for Error {
+    #[cfg(feature = "std")]
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        self.0.io.as_ref().map(|e| e as _)
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl ser::Error for Error {
+    fn custom<T: core::fmt::Display>(msg: T) -> Self {
+        Error::new(ErrorKind::Custom, msg)
+    }
+}
+
+#[cfg(feature = "std")]
+impl From<std::io::Error> for Error {
+    fn from(e: std::io::Error) -> Self {
+        let mut error = Error::new(ErrorKind::Io, &e);
+        error.0.io = Some(e);
+        error
+    }
+}
+
+/// Wrapper for any [core::fmt::Write], e.g. a [core::fmt::Formatter]
+pub struct FmtEat<W>(pub W);
+impl<W: core::fmt::Write> Eat for FmtEat<W> {
+    type Error = core::fmt::Error;
+
+    fn eat(&mut self, data: &str) -> Result<(), Self::Error> {
+        self.0.write_str(data)
+    }
+}
+
+/// Wrapper adding ANSI color escape codes around tokens, for terminals
+///
+/// Escape codes don't count towards line widths or the [Limit].
+pub struct AnsiEat<E> {
+    /// Where the colored output goes
+    pub eat: E,
+    /// Which colors to use
+    pub styles: AnsiStyles,
+}
+
+impl<E> AnsiEat<E> {
+    /// Color with [AnsiStyles::default]
+    pub fn new(eat: E) -> Self {
+        AnsiEat {
+            eat,
+            styles: AnsiStyles::default(),
+        }
+    }
+}
+
+impl<E: Eat> Eat for AnsiEat<E> {
+    type Error = E::Error;
+
+    fn eat(&mut self, data: &str) -> Result<(), Self::Error> {
+        self.eat.eat(data)
+    }
+
+    fn error(kind: ErrorKind, msg: &'static str) -> Self::Error {
+        E::error(kind, msg)
+    }
+
+    fn locate(error: Self::Error, at: Segment<'_>) -> Self::Error {
+        E::locate(error, at)
+    }
+
+    fn wants_events(&self) -> bool {
+        true
+    }
+
+    fn event(&mut self, event: Event<'_>) -> Result<(), Self::Error> {
+        match event {
+            Event::Begin { token, .. } => {
+                self.color(token)?;
+                self.eat.event(event)
+            }
+            Event::End(token) => {
+                self.eat.event(event)?;
+                self.uncolor(token)
+            }
+            _ if event.punct().is_some() => {
+                self.color(Token::Punct)?;
+                self.eat.event(event)?;
+                self.uncolor(Token::Punct)
+            }
+            _ => self.eat.event(event),
+        }
+    }
+}
+
+impl<E: Eat> AnsiEat<E> {
+    fn color(&mut self, token: Token) -> Result<(), E::Error> {
+        let style = self.styles.get(token);
+        if !style.is_empty() {
+            self.eat.eat("\x1b[")?;
+            self.eat.eat(style)?;
+            self.eat.eat("m")?;
+        }
+        Ok(())
+    }
+
+    fn uncolor(&mut self, token: Token) -> Result<(), E::Error> {
+        match self.styles.get(token).is_empty() {
+            true => Ok(()),
+            false => self.eat.eat("\x1b[0m"),
+        }
+    }
+}
+
+/// SGR parameters for each kind of [Token], e.g. `"1;34"` for bold blue, empty for no color
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AnsiStyles {
+    /// For [Token::Key]
+    pub key: &'static str,
+    /// For [Token::Str]
+    pub str: &'static str,
+    /// For [Token::Num]
+    pub num: &'static str,
+    /// For [Token::Literal]
+    pub literal: &'static str,
+    /// For [Token::Tag]
+    pub tag: &'static str,
+    /// For [Token::Punct]
+    pub punct: &'static str,
+}
+
+impl AnsiStyles {
+    fn get(&self, token: Token) -> &'static str {
+        match token {
+            Token::Key => self.key,
+            Token::Str => self.str,
+            Token::Num => self.num,
+            Token::Literal => self.literal,
+            Token::Tag => self.tag,
+            Token::Punct => self.punct,
+        }
+    }
+}
+
+/// Blue keys, green strings, cyan numbers, magenta literals, yellow tags, and uncolored punctuation
+impl Default for AnsiStyles {
+    fn default() -> Self {
+        AnsiStyles {
+            key: "34",
+            str: "32",
+            num: "36",
+            literal: "35",
+            tag: "33",
+            punct: "",
+        }
+    }
+}
+
+/// Wrapper producing HTML, with tokens in `<span class="key">` etc., see [Token::name]
+///
+/// Wrap the output in a `<pre>` or style it with `white-space: pre` to keep the line breaks.
+/// Escaping `&<>"'` doesn't count towards line widths or the [Limit].
+pub struct HtmlEat<E>(pub E);
+
+impl<E: Eat> Eat for HtmlEat<E> {
+    type Error = E::Error;
+
+    fn eat(&mut self, data: &str) -> Result<(), Self::Error> {
+        let mut run = 0;
+        for (i, b) in data.bytes().enumerate() {
+            let entity = match b {
+                b'&' => "&amp;",
+                b'<' => "&lt;",
+                b'>' => "&gt;",
+                b'"' => "&quot;",
+                b'\'' => "&#39;",
+                _ => continue,
+            };
+            self.0.eat(&data[run..i])?;
+            self.0.eat(entity)?;
+            run = i + 1;
+        }
+        self.0.eat(&data[run..])
+    }
+
+    fn error(kind: ErrorKind, msg: &'static str) -> Self::Error {
+        E::error(kind, msg)
+    }
+
+    fn locate(error: Self::Error, at: Segment<'_>) -> Self::Error {
+        E::locate(error, at)
+    }
+
+    fn wants_events(&self) -> bool {
+        true
+    }
+
+    fn event(&mut self, event: Event<'_>) -> Result<(), Self::Error> {
+        match event {
+            Event::Begin { token, .. } => self.open(token),
+            Event::End(_) => self.0.eat("</span>"),
+            _ if event.punct().is_some() => {
+                self.open(Token::Punct)?;
+                event.write_to(&mut self.0)?;
+                self.0.eat("</span>")
+            }
+            _ => event.write_to(self),
+        }
+    }
+}
+
+impl<E: Eat> HtmlEat<E> {
+    fn open(&mut self, token: Token) -> Result<(), E::Error> {
+        self.0.eat("<span class=\"")?;
+        self.0.eat(token.name())?;
+        self.0.eat("\">")
+    }
+}
+
+#[cfg(feature = "alloc")]
+/// Wrapper recording the [Span] of the output and of each element and entry in it, see [to_string_with_spans]
+pub struct SpanEat<E> {
+    /// Where the output goes
+    pub eat: E,
+    spans: Vec<Span>,
+    /// Index in `spans` and length of the parent's path for each node that hasn't ended
+    open: Vec<(usize, usize)>,
+    path: String,
+    at: Position,
+}
+
+#[cfg(feature = "alloc")]
+impl<E> SpanEat<E> {
+    /// Start recording at the start of the output
+    pub fn new(eat: E) -> Self {
+        let at = Position {
+            offset: 0,
+            line: 1,
+            column: 1,
+        };
+        SpanEat {
+            eat,
+            spans: alloc::vec![Span {
+                path: String::new(),
+                start: at,
+                end: at,
+            }],
+            open: Vec::new(),
+            path: String::new(),
+            at,
+        }
+    }
+
+    /// Where the next output goes
+    pub fn position(&self) -> Position {
+        self.at
+    }
+
+    /// The output, and the spans so far, with the ones that haven't ended, e.g. after errors, ending here
+    pub fn finish(mut self) -> (E, Vec<Span>) {
+        self.spans[0].end = self.at;
+        for (i, _) in self.open {
+            self.spans[i].end = self.at;
+        }
+        (self.eat, self.spans)
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl<E: Eat> Eat for SpanEat<E> {
+    type Error = E::Error;
+
+    fn eat(&mut self, data: &str) -> Result<(), Self::Error> {
+        self.event(Event::Text(data))
+    }
+
+    fn error(kind: ErrorKind, msg: &'static str) -> Self::Error {
+        E::error(kind, msg)
+    }
+
+    fn locate(error: Self::Error, at: Segment<'_>) -> Self::Error {
+        E::locate(error, at)
+    }
+
+    fn wants_events(&self) -> bool {
+        true
+    }
+
+    fn event(&mut self, event: Event<'_>) -> Result<(), Self::Error> {
+        self.eat.event(event)?;
+        match event {
+            Event::Enter(at) => {
+                self.open.push((self.spans.len(), self.path.len()));
+                match at {
+                    Segment::Index(i) => {
+                        use core::fmt::Write;
+                        let _ = write!(self.path, "[{i}]");
+                    }
+                    Segment::Key(key) => {
+                        if !self.path.is_empty() {
+                            self.path.push('.');
+                        }
+                        self.path.push_str(key);
+                    }
+                }
+                self.spans.push(Span {
+                    path: self.path.clone(),
+                    start: self.at,
+                    end: self.at,
+                });
+            }
+            Event::Leave => {
+                if let Some((i, len)) = self.open.pop() {
+                    self.spans[i].end = self.at;
+                    self.path.truncate(len);
+                }
+            }
+            _ => {
+                let _ = event.write_to(&mut Advance(&mut self.at));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(feature = "alloc")]
+/// Where a node is in the output, see [SpanEat]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Span {
+    /// Keys and indices leading to the node, like [Error::path], e.g. `spec.containers[2].env`, empty at the top level
+    pub path: String,
+    /// Where the node starts
+    pub start: Position,
+    /// Just after where the node ends
+    pub end: Position,
+}
+
+#[cfg(feature = "alloc")]
+/// Place in the output, see [Span]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Position {
+    /// Bytes before it
+    pub offset: usize,
+    /// Line, counting from 1
+    pub line: usize,
+    /// Characters before it on its line, plus 1
+    pub column: usize,
+}
+
+#[cfg(feature = "alloc")]
+/// Moves the [Position] along what it eats
+struct Advance<'a>(&'a mut Position);
+
+#[cfg(feature = "alloc")]
+impl Eat for Advance<'_> {
+    type Error = core::fmt::Error;
+
+    fn eat(&mut self, data: &str) -> Result<(), Self::Error> {
+        let at = &mut *self.0;
+        at.offset += data.len();
+        match data.rfind('\n') {
+            Some(i) => {
+                at.line += data.matches('\n').count();
+                at.column = 1 + data[i + 1..].chars().count();
+            }
+            None => at.column += data.chars().count(),
+        }
+        Ok(())
+    }
+}
+
+/// Format `value` with [Display][core::fmt::Display], e.g. `format!("{}", cyrly::display(&value))`
+///
+/// Writes on a single line, or multiline with the alternate flag: `{:#}`.
+/// Serialization errors turn into [core::fmt::Error], which makes `format!` and `to_string` panic.
+pub fn display<T: Serialize + ?Sized>(value: &T) -> Display<'_, T> {
+    Display(value)
+}
+
+/// Wrapper returned by [display]
+///
+/// A precision caps the output length like [to_string_truncated] does, e.g. `{:.200}`.
+pub struct Display<'a, T: ?Sized>(&'a T);
+impl<T: Serialize + ?Sized> core::fmt::Display for Display<'_, T> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        let multiline = f.alternate();
+        let precision = f.precision();
+        let mut glut = FmtEat(f);
+        if let Some(max_len) = precision {
+            return serialize_truncated(self.0, &mut glut, multiline, max_len);
+        }
+        let mut ser = CurlySerializer::new(&mut glut);
+        ser.multiline = multiline;
+        self.0.serialize(ser)
+    }
 }
 
 #[cfg(feature = "std")]
@@ -84,31 +954,18 @@ pub mod write {
     /// Write wrapper
     pub struct WriteEat<T>(pub T);
     impl<T: std::io::Write> Eat for WriteEat<T> {
-        type Error = WriteEatError;
+        type Error = Error;
 
         fn eat(&mut self, data: &str) -> Result<(), Self::Error> {
-            self.0.write_all(data.as_bytes()).map_err(WriteEatError)
+            Ok(self.0.write_all(data.as_bytes())?)
         }
-    }
 
-    /// Error wrapper
-    #[derive(Debug)]
-    pub struct WriteEatError(pub std::io::Error);
-    impl std::fmt::Display for WriteEatError {
-        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-            self.0.fmt(f)
+        fn error(kind: ErrorKind, msg: &'static str) -> Self::Error {
+            Error::new(kind, msg)
         }
-    }
-    impl std::error::Error for WriteEatError {}
-    impl serde::ser::Error for WriteEatError {
-        fn custom<T>(msg: T) -> Self
-        where
-            T: std::fmt::Display,
-        {
-            WriteEatError(std::io::Error::new(
-                std::io::ErrorKind::Other,
-                format!("{msg}"),
-            ))
+
+        fn locate(error: Self::Error, at: Segment<'_>) -> Self::Error {
+            error.locate(at)
         }
     }
 }
@@ -125,46 +982,82 @@ impl<'e, E: Eat> Serializer for CurlySerializer<'e, E> {
     type SerializeStructVariant = CurlyMap<'e, E>;
 
     fn collect_str<T: core::fmt::Display + ?Sized>(
-        self,
+        mut self,
         v: &T,
     ) -> Result<<Self as Serializer>::Ok, <Self as Serializer>::Error> {
-        self.serialize_str(&v.to_string())
+        use core::fmt::Write;
+        let mut buf = StackStr::<COLLECT_MAX>::new();
+        if write!(buf, "{v}").is_ok() {
+            return self.serialize_str(buf.as_str());
+        }
+        if self.key && self.strict && !self.schema.is_json() {
+            return Err(E::error(
+                ErrorKind::Unsupported,
+                "strict: key longer than 1024 bytes",
+            ));
+        }
+        // Too long to look at as a whole, so quote it and escape it as it comes
+        let token = match self.key {
+            true => Token::Key,
+            false => Token::Str,
+        };
+        self.token(token, ScalarStyle::Quoted, |ser| {
+            ser.eat_quote(true)?;
+            let mut escape = Escape { ser, error: None };
+            if write!(escape, "{v}").is_err() {
+                return Err(escape.error.unwrap_or_else(|| {
+                    E::error(
+                        ErrorKind::Custom,
+                        "collect_str: Display implementation failed",
+                    )
+                }));
+            }
+            ser.eat_quote(false)
+        })
+    }
+
+    fn serialize_bool(self, v: bool) -> Result<Self::Ok, Self::Error> {
+        self.serialize_scalar(if v { "true" } else { "false" }, Token::Literal)
     }
 
-    fn serialize_bool(mut self, v: bool) -> Result<Self::Ok, Self::Error> {
-        self.eat(&v.to_string())
+    fn serialize_i8(self, v: i8) -> Result<Self::Ok, Self::Error> {
+        self.serialize_scalar(itoa::Buffer::new().format(v), Token::Num)
     }
 
-    fn serialize_i8(mut self, v: i8) -> Result<Self::Ok, Self::Error> {
-        self.eat(&v.to_string())
+    fn serialize_i16(self, v: i16) -> Result<Self::Ok, Self::Error> {
+        self.serialize_scalar(itoa::Buffer::new().format(v), Token::Num)
     }
 
-    fn serialize_i16(mut self, v: i16) -> Result<Self::Ok, Self::Error> {
-        self.eat(&v.to_string())
+    fn serialize_i32(self, v: i32) -> Result<Self::Ok, Self::Error> {
+        self.serialize_scalar(itoa::Buffer::new().format(v), Token::Num)
     }
 
-    fn serialize_i32(mut self, v: i32) -> Result<Self::Ok, Self::Error> {
-        self.eat(&v.to_string())
+    fn serialize_i64(self, v: i64) -> Result<Self::Ok, Self::Error> {
+        self.serialize_scalar(itoa::Buffer::new().format(v), Token::Num)
     }
 
-    fn serialize_i64(mut self, v: i64) -> Result<Self::Ok, Self::Error> {
-        self.eat(&v.to_string())
+    fn serialize_i128(self, v: i128) -> Result<Self::Ok, Self::Error> {
+        self.serialize_scalar(itoa::Buffer::new().format(v), Token::Num)
     }
 
-    fn serialize_u8(mut self, v: u8) -> Result<Self::Ok, Self::Error> {
-        self.eat(&v.to_string())
+    fn serialize_u8(self, v: u8) -> Result<Self::Ok, Self::Error> {
+        self.serialize_scalar(itoa::Buffer::new().format(v), Token::Num)
     }
 
-    fn serialize_u16(mut self, v: u16) -> Result<Self::Ok, Self::Error> {
-        self.eat(&v.to_string())
+    fn serialize_u16(self, v: u16) -> Result<Self::Ok, Self::Error> {
+        self.serialize_scalar(itoa::Buffer::new().format(v), Token::Num)
     }
 
-    fn serialize_u32(mut self, v: u32) -> Result<Self::Ok, Self::Error> {
-        self.eat(&v.to_string())
+    fn serialize_u32(self, v: u32) -> Result<Self::Ok, Self::Error> {
+        self.serialize_scalar(itoa::Buffer::new().format(v), Token::Num)
     }
 
-    fn serialize_u64(mut self, v: u64) -> Result<Self::Ok, Self::Error> {
-        self.eat(&v.to_string())
+    fn serialize_u64(self, v: u64) -> Result<Self::Ok, Self::Error> {
+        self.serialize_scalar(itoa::Buffer::new().format(v), Token::Num)
+    }
+
+    fn serialize_u128(self, v: u128) -> Result<Self::Ok, Self::Error> {
+        self.serialize_scalar(itoa::Buffer::new().format(v), Token::Num)
     }
 
     fn serialize_f32(self, v: f32) -> Result<Self::Ok, Self::Error> {
@@ -186,104 +1079,46 @@ impl<'e, E: Eat> Serializer for CurlySerializer<'e, E> {
     }
 
     fn serialize_char(self, v: char) -> Result<Self::Ok, Self::Error> {
-        self.serialize_str(&[v].iter().collect::<String>())
+        self.serialize_str(v.encode_utf8(&mut [0u8; 4]))
     }
 
     fn serialize_str(mut self, v: &str) -> Result<Self::Ok, Self::Error> {
-        if is_yaml_special_str(v) {
-            self.eat("\"")?;
-            self.eat(v)?;
-            self.eat("\"")?;
+        let json = self.schema.is_json();
+        let token = match self.key {
+            true => Token::Key,
+            false => Token::Str,
+        };
+        if self.key && self.strict && !json {
+            // YAML 1.1 limits implicit keys to 1024 characters, and strict doesn't allow "? ".
+            // Count bytes of the output instead, which are never fewer.
+            self.key = false;
+            return match v.len() <= 1024 && self.serialize_short(v, 1024, None, Some(token))? {
+                true => Ok(()),
+                false => Err(E::error(
+                    ErrorKind::Unsupported,
+                    "strict: key longer than 1024 bytes",
+                )),
+            };
+        } else if self.key && self.schema == Schema::Json5 && is_json5_identifier(v) {
+            self.token(token, ScalarStyle::Plain, |ser| ser.eat(v))?;
+        } else if json || self.schema.is_special_str(v) {
+            // Timestamps may go on with anything, quotes and control characters included
+            self.token(token, ScalarStyle::Quoted, |ser| {
+                ser.serialize_quoted_str(v)
+            })?;
         } else if self.multiline {
-            if let Some(shawt) = self.serialize_short(v, 80) {
-                self.eat(&shawt)?;
-            } else {
-                self.eat("\"")?;
-                let mut chars_on_line = usize::MAX;
-                let mut toks = WordOrSpace(v).peekable();
-                while let Some(tok) = toks.next() {
-                    match tok {
-                        " " => {
-                            // Newline "early" if the next word wouldn't fit onto the current line - but only up to some sensible length
-                            // Working with length here is a crude approximation
-                            let next_len = toks
-                                .peek()
-                                .map(|s| s.len())
-                                .filter(|&l| l < 60)
-                                .unwrap_or(0);
-                            if chars_on_line.saturating_add(next_len) >= 80 {
-                                assert!(self.multiline);
-                                chars_on_line = 0;
-                                self.indent(false)?;
-                                match toks.peek() {
-                                    Some(&" ") => {
-                                        self.eat(" \\ ")?;
-                                        toks.next();
-                                    }
-                                    _ => self.eat("  ")?,
-                                }
-                            } else {
-                                match toks.peek() {
-                                    Some(&" ") => {
-                                        self.eat(" ")?;
-                                        while toks.peek() == Some(&" ") {
-                                            self.eat(" ")?;
-                                            if chars_on_line >= 80 {
-                                                self.eat("\\")?;
-                                                break;
-                                            }
-                                            chars_on_line += 1;
-                                            toks.next();
-                                        }
-                                    }
-                                    Some(_) => self.eat(tok)?,
-                                    None => self.indent(false)?,
-                                }
-                            }
-                        }
-                        "\n" => {
-                            self.eat("\\n")?;
-                            chars_on_line = usize::MAX;
-                            match toks.peek() {
-                                Some(&"\n") => {
-                                    self.eat("\\")?;
-                                    self.indent(true)?
-                                }
-                                None => {
-                                    self.eat("\\")?;
-                                    self.indent(false)?
-                                }
-                                _ => (),
-                            }
-                        }
-                        a => {
-                            for c in a.chars() {
-                                if chars_on_line >= 80 {
-                                    self.eat("\\")?;
-                                    self.indent(true)?;
-                                    chars_on_line = 0;
-                                }
-                                assert!(c != ' ');
-                                self.serialize_char_in_string(c)?;
-                                chars_on_line += 1;
-                            }
-                            if toks.peek().is_none() {
-                                self.eat("\\")?;
-                                self.indent(false)?;
-                            }
-                        }
-                    }
-                }
-                self.eat("\"")?;
+            // Output is never shorter than the string itself
+            if !(v.len() <= 80 && self.serialize_short(v, 80, None, Some(token))?) {
+                self.token(token, ScalarStyle::Multiline, |ser| {
+                    ser.serialize_multiline_str(v)
+                })?;
             }
         } else if is_yaml_benign_str(v) {
-            self.eat(v)?;
+            self.token(token, ScalarStyle::Plain, |ser| ser.eat(v))?;
         } else {
-            self.eat("\"")?;
-            for c in v.chars() {
-                self.serialize_char_in_string(c)?;
-            }
-            self.eat("\"")?;
+            self.token(token, ScalarStyle::Quoted, |ser| {
+                ser.serialize_quoted_str(v)
+            })?;
         }
         Ok(())
     }
@@ -296,13 +1131,11 @@ impl<'e, E: Eat> Serializer for CurlySerializer<'e, E> {
     }
 
     fn serialize_none(mut self) -> Result<Self::Ok, Self::Error> {
-        self.eat("null")
+        self.refuse_non_str_key()?;
+        self.token(Token::Literal, ScalarStyle::Plain, |ser| ser.eat("null"))
     }
 
-    fn serialize_some<T: ?Sized>(self, value: &T) -> Result<Self::Ok, Self::Error>
-    where
-        T: serde::Serialize,
-    {
+    fn serialize_some<T: ?Sized + Serialize>(self, value: &T) -> Result<Self::Ok, Self::Error> {
         value.serialize(self)
     }
 
@@ -323,49 +1156,31 @@ impl<'e, E: Eat> Serializer for CurlySerializer<'e, E> {
         self.serialize_str(variant)
     }
 
-    fn serialize_newtype_struct<T: ?Sized>(
+    fn serialize_newtype_struct<T: ?Sized + Serialize>(
         self,
-        _name: &'static str,
+        name: &'static str,
         value: &T,
-    ) -> Result<Self::Ok, Self::Error>
-    where
-        T: serde::Serialize,
-    {
+    ) -> Result<Self::Ok, Self::Error> {
+        #[cfg(feature = "alloc")]
+        if name == value::TAGGED {
+            return value.serialize(value::TagSerializer(self));
+        }
+        #[cfg(not(feature = "alloc"))]
+        let _ = name;
         value.serialize(self)
     }
 
-    fn serialize_newtype_variant<T: ?Sized>(
-        mut self,
+    fn serialize_newtype_variant<T: ?Sized + Serialize>(
+        self,
         _name: &'static str,
         _variant_index: u32,
         variant: &'static str,
         value: &T,
-    ) -> Result<Self::Ok, Self::Error>
-    where
-        T: serde::Serialize,
-    {
-        self.serialize_variant_name(variant)?;
-        value.serialize(self)
+    ) -> Result<Self::Ok, Self::Error> {
+        self.serialize_tagged(variant, value)
     }
 
     fn serialize_tuple(self, len: usize) -> Result<Self::SerializeTuple, Self::Error> {
-        impl<E: Eat> SerializeTuple for CurlySeq<'_, E> {
-            type Ok = ();
-
-            type Error = <E as Eat>::Error;
-
-            fn serialize_element<T: ?Sized>(&mut self, value: &T) -> Result<(), Self::Error>
-            where
-                T: serde::Serialize,
-            {
-                SerializeSeq::serialize_element(self, value)
-            }
-
-            fn end(self) -> Result<Self::Ok, Self::Error> {
-                SerializeSeq::end(self)
-            }
-        }
-
         self.serialize_seq(Some(len))
     }
 
@@ -374,23 +1189,6 @@ impl<'e, E: Eat> Serializer for CurlySerializer<'e, E> {
         _name: &'static str,
         len: usize,
     ) -> Result<Self::SerializeTupleStruct, Self::Error> {
-        impl<E: Eat> SerializeTupleStruct for CurlySeq<'_, E> {
-            type Ok = ();
-
-            type Error = <E as Eat>::Error;
-
-            fn serialize_field<T: ?Sized>(&mut self, value: &T) -> Result<(), Self::Error>
-            where
-                T: serde::Serialize,
-            {
-                SerializeTuple::serialize_element(self, value)
-            }
-
-            fn end(self) -> Result<Self::Ok, Self::Error> {
-                SerializeTuple::end(self)
-            }
-        }
-
         self.serialize_tuple(len)
     }
 
@@ -399,27 +1197,10 @@ impl<'e, E: Eat> Serializer for CurlySerializer<'e, E> {
         _name: &'static str,
         _variant_index: u32,
         variant: &'static str,
-        len: usize,
+        _len: usize,
     ) -> Result<Self::SerializeTupleVariant, Self::Error> {
-        impl<E: Eat> SerializeTupleVariant for CurlySeq<'_, E> {
-            type Ok = ();
-
-            type Error = <E as Eat>::Error;
-
-            fn serialize_field<T: ?Sized>(&mut self, value: &T) -> Result<(), Self::Error>
-            where
-                T: serde::Serialize,
-            {
-                SerializeTuple::serialize_element(self, value)
-            }
-
-            fn end(self) -> Result<Self::Ok, Self::Error> {
-                SerializeTuple::end(self)
-            }
-        }
-
-        self.serialize_variant_name(variant)?;
-        self.serialize_tuple(len)
+        let variant = self.serialize_variant_name(variant)?;
+        CurlySeq::new(self, variant)
     }
 
     fn serialize_struct(
@@ -427,83 +1208,146 @@ impl<'e, E: Eat> Serializer for CurlySerializer<'e, E> {
         _name: &'static str,
         len: usize,
     ) -> Result<Self::SerializeStruct, Self::Error> {
-        impl<E: Eat> SerializeStruct for CurlyMap<'_, E> {
-            type Ok = ();
-
-            type Error = <E as Eat>::Error;
-
-            fn serialize_field<T: ?Sized>(
-                &mut self,
-                key: &'static str,
-                value: &T,
-            ) -> Result<(), Self::Error>
-            where
-                T: serde::Serialize,
-            {
-                SerializeMap::serialize_key(self, key)?;
-                SerializeMap::serialize_value(self, value)?;
-                Ok(())
-            }
-
-            fn end(self) -> Result<Self::Ok, Self::Error> {
-                SerializeMap::end(self)
-            }
-        }
-
         self.serialize_map(Some(len))
     }
 
     fn serialize_struct_variant(
         mut self,
-        name: &'static str,
+        _name: &'static str,
         _variant_index: u32,
         variant: &'static str,
-        len: usize,
+        _len: usize,
     ) -> Result<Self::SerializeStructVariant, Self::Error> {
-        impl<E: Eat> SerializeStructVariant for CurlyMap<'_, E> {
-            type Ok = ();
-
-            type Error = <E as Eat>::Error;
-
-            fn serialize_field<T: ?Sized>(
-                &mut self,
-                key: &'static str,
-                value: &T,
-            ) -> Result<(), Self::Error>
-            where
-                T: serde::Serialize,
-            {
-                SerializeStruct::serialize_field(self, key, value)
-            }
-
-            fn end(self) -> Result<Self::Ok, Self::Error> {
-                SerializeStruct::end(self)
-            }
-        }
-
-        self.serialize_variant_name(variant)?;
-        self.serialize_struct(name, len)
+        let variant = self.serialize_variant_name(variant)?;
+        CurlyMap::new(self, variant)
     }
 
     fn serialize_seq(self, _len: Option<usize>) -> Result<Self::SerializeSeq, Self::Error> {
-        CurlySeq::new(self)
+        CurlySeq::new(self, None)
     }
 
     fn serialize_map(self, _len: Option<usize>) -> Result<Self::SerializeMap, Self::Error> {
-        CurlyMap::new(self)
+        CurlyMap::new(self, None)
+    }
+}
+
+impl<E: Eat> SerializeTuple for CurlySeq<'_, E> {
+    type Ok = ();
+
+    type Error = <E as Eat>::Error;
+
+    fn serialize_element<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<(), Self::Error> {
+        SerializeSeq::serialize_element(self, value)
+    }
+
+    fn end(self) -> Result<Self::Ok, Self::Error> {
+        SerializeSeq::end(self)
+    }
+}
+
+impl<E: Eat> SerializeTupleStruct for CurlySeq<'_, E> {
+    type Ok = ();
+
+    type Error = <E as Eat>::Error;
+
+    fn serialize_field<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<(), Self::Error> {
+        SerializeTuple::serialize_element(self, value)
+    }
+
+    fn end(self) -> Result<Self::Ok, Self::Error> {
+        SerializeTuple::end(self)
+    }
+}
+
+impl<E: Eat> SerializeTupleVariant for CurlySeq<'_, E> {
+    type Ok = ();
+
+    type Error = <E as Eat>::Error;
+
+    fn serialize_field<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<(), Self::Error> {
+        SerializeTuple::serialize_element(self, value)
+    }
+
+    fn end(self) -> Result<Self::Ok, Self::Error> {
+        SerializeTuple::end(self)
+    }
+}
+
+impl<E: Eat> SerializeStruct for CurlyMap<'_, E> {
+    type Ok = ();
+
+    type Error = <E as Eat>::Error;
+
+    fn serialize_field<T: ?Sized + Serialize>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), Self::Error> {
+        SerializeMap::serialize_key(self, key)?;
+        self.value(value, |glut| glut.event(Event::Enter(Segment::Key(key))))
+            .map_err(|e| E::locate(e, Segment::Key(key)))
+    }
+
+    fn end(self) -> Result<Self::Ok, Self::Error> {
+        SerializeMap::end(self)
+    }
+}
+
+impl<E: Eat> SerializeStructVariant for CurlyMap<'_, E> {
+    type Ok = ();
+
+    type Error = <E as Eat>::Error;
+
+    fn serialize_field<T: ?Sized + Serialize>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), Self::Error> {
+        SerializeStruct::serialize_field(self, key, value)
+    }
+
+    fn end(self) -> Result<Self::Ok, Self::Error> {
+        SerializeStruct::end(self)
     }
 }
 
+/// Whether `v` can be written as a plain (unquoted) scalar inside a flow collection
+///
+/// This is `ns-plain-first(flow-in) ns-plain-char(flow-in)*` from the YAML 1.2 spec,
+/// restricted to a single word so neither line folding nor ` #` comments come into play.
+/// `:` and `?` are refused entirely: older libyaml and PyYAML end flow plain scalars on them.
+///
+/// Only checks the syntax, [Schema::is_special_str] has to be checked separately.
 fn is_yaml_benign_str(v: &str) -> bool {
-    v.chars().next().map_or(false, char::is_alphabetic)
-        && v.chars().all(|v| v.is_ascii_alphanumeric() || v == '_')
+    let plain_first = match v.chars().next() {
+        Some('-') => v.len() > 1 && !v.starts_with("---"),
+        Some(c) => !is_yaml_indicator(c),
+        None => false,
+    };
+    plain_first && v.chars().all(is_yaml_plain_safe)
+}
+
+/// Characters [CurlySerializer::serialize_char_in_string] would write as they are in every schema
+fn is_verbatim_in_string(c: char) -> bool {
+    c == ' ' || ((c.is_ascii_graphic() || c.is_alphanumeric()) && c != '"' && c != '\\')
+}
+
+/// ECMAScript 5 `IdentifierName`, restricted to ASCII
+fn is_json5_identifier(v: &str) -> bool {
+    v.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_' || c == '$')
+        && v.chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '$')
+}
+
+/// `c-indicator` from the YAML spec
+fn is_yaml_indicator(c: char) -> bool {
+    "-?:,[]{}#&*!|>'\"%@`".contains(c)
 }
 
-fn is_yaml_special_str(v: &str) -> bool {
-    matches!(
-        v.to_ascii_lowercase().as_str(),
-        "y" | "yes" | "n" | "no" | "true" | "false" | "on" | "off" | "null" | "nan" | "inf"
-    )
+/// `ns-plain-safe(flow-in)`, minus anything [CurlySerializer::serialize_char_in_string] would escape
+fn is_yaml_plain_safe(c: char) -> bool {
+    (c.is_ascii_graphic() || c.is_alphanumeric())
+        && !matches!(c, ',' | '[' | ']' | '{' | '}' | ':' | '?')
 }
 
 impl<'e, E: Eat> CurlySerializer<'e, E> {
@@ -512,39 +1356,136 @@ impl<'e, E: Eat> CurlySerializer<'e, E> {
         Self {
             level: 0,
             multiline: true,
+            schema: Schema::default(),
+            strict: false,
+            limit: None,
+            max_depth: None,
+            max_elements: None,
+            key: false,
             glut,
             max_output: None,
+            widths: None,
         }
     }
 
-    fn serialize_variant_name(&mut self, variant: &str) -> Result<(), <E as Eat>::Error> {
-        self.eat("!")?;
-        self.eat(&urlencoding::encode(variant))?;
-        self.eat(" ")?;
-        Ok(())
+    /// `!variant value`, or in JSON, a single-entry map with the variant name as key
+    fn serialize_tagged<T: ?Sized + Serialize>(
+        mut self,
+        variant: &str,
+        value: &T,
+    ) -> Result<(), <E as Eat>::Error> {
+        let reservation = match self.serialize_variant_name(variant)? {
+            Some(reservation) => reservation,
+            None => return value.serialize(self),
+        };
+        if let Err(e) = value.serialize(self.reborrow()) {
+            if self.truncated() {
+                self.eat_cut_marker(Cut::Value, false)?;
+                self.close_variant(reservation)?;
+            }
+            return Err(e);
+        }
+        self.close_variant(reservation)
     }
 
-    fn indent(&mut self, extra: bool) -> Result<(), <E as Eat>::Error> {
-        if self.multiline {
-            self.eat("\n")?;
-            for _ in 0..self.level {
-                self.eat("  ")?;
+    /// Tag for the variant, or in JSON, the start of a single-entry map with the variant name as key,
+    /// which [CurlySerializer::close_variant] ends after the variant's data
+    fn serialize_variant_name(
+        &mut self,
+        variant: &str,
+    ) -> Result<Option<Reservation>, <E as Eat>::Error> {
+        if self.schema.is_json() {
+            // Check the space for the variant data's opening bracket too, so the output isn't cut between the two
+            self.refuse_non_str_key()?;
+            let name_len = 1 + self.indent_len(true) + 6 * variant.len() + 4;
+            let closing_len = 1 + self.closing_len(self.level);
+            self.fits(name_len + closing_len + self.open_len(self.level + 1, 1))?;
+            let mut reservation = self.start(Event::MapStart)?;
+            // The `,` after the data
+            if let Some(limit) = self.limit.as_deref_mut() {
+                limit.reserved += 1;
+                reservation.closing += 1;
             }
-            if extra {
-                self.eat("  ")?;
+            self.indent(true)?;
+            let mut name = self.next_level();
+            name.key = true;
+            name.serialize_str(variant)?;
+            self.event(Event::Colon)?;
+            self.level += 1;
+            return Ok(Some(reservation));
+        }
+        if self.strict {
+            return Err(E::error(
+                ErrorKind::Unsupported,
+                "strict: tags (enum variants with data) are not allowed",
+            ));
+        }
+        self.fits(2 + 3 * variant.len())?;
+        self.token(Token::Tag, ScalarStyle::Plain, |ser| {
+            ser.eat("!")?;
+            ser.eat_percent_encoded(variant)
+        })?;
+        self.event(Event::Space)?;
+        Ok(None)
+    }
+
+    /// End of the map [CurlySerializer::serialize_variant_name] started
+    fn close_variant(&mut self, reservation: Reservation) -> Result<(), <E as Eat>::Error> {
+        if self.trailing_comma() {
+            self.event(Event::Comma)?;
+        }
+        self.level -= 1;
+        self.end(Event::MapEnd, false, reservation, None)
+    }
+
+    /// Everything but ASCII alphanumerics and `-._~` as `%XX`, the same as the urlencoding crate does it
+    fn eat_percent_encoded(&mut self, v: &str) -> Result<(), <E as Eat>::Error> {
+        let bytes = v.as_bytes();
+        let mut run = 0;
+        for (i, &b) in bytes.iter().enumerate() {
+            if b.is_ascii_alphanumeric() || matches!(b, b'-' | b'.' | b'_' | b'~') {
+                continue;
             }
-        } else {
-            self.eat(" ")?;
+            self.eat(core::str::from_utf8(&bytes[run..i]).expect("ASCII"))?;
+            let hex = b"0123456789ABCDEF";
+            let enc = [b'%', hex[usize::from(b >> 4)], hex[usize::from(b & 15)]];
+            self.eat(core::str::from_utf8(&enc).expect("ASCII"))?;
+            run = i + 1;
+        }
+        self.eat(core::str::from_utf8(&bytes[run..]).expect("ASCII"))
+    }
+
+    fn indent(&mut self, extra: bool) -> Result<(), <E as Eat>::Error> {
+        match self.multiline {
+            true => self.event(Event::Newline {
+                level: self.level + extra as usize,
+            }),
+            false => self.event(Event::Space),
         }
-        Ok(())
     }
 
-    fn next_level(&mut self) -> CurlySerializer<E> {
+    fn next_level(&mut self) -> CurlySerializer<'_, E> {
         CurlySerializer {
             level: self.level + 1,
             multiline: self.multiline,
+            schema: self.schema,
+            strict: self.strict,
+            limit: self.limit.as_deref_mut(),
+            max_depth: self.max_depth,
+            max_elements: self.max_elements,
+            key: self.key,
             glut: self.glut,
             max_output: self.max_output.as_deref_mut(),
+            widths: self.widths.as_deref_mut(),
+        }
+    }
+
+    /// The same serializer, for writing a value with something following it
+    fn reborrow(&mut self) -> CurlySerializer<'_, E> {
+        let level = self.level;
+        CurlySerializer {
+            level,
+            ..self.next_level()
         }
     }
 
@@ -556,70 +1497,351 @@ impl<'e, E: Eat> CurlySerializer<'e, E> {
         }
     }
 
-    /// Convenience function. Builders are overrated.
-    pub fn oneline(self) -> Self {
-        CurlySerializer {
-            multiline: false,
-            ..self
+    /// Convenience function. Builders are overrated.
+    pub fn oneline(self) -> Self {
+        CurlySerializer {
+            multiline: false,
+            ..self
+        }
+    }
+
+    fn start(&mut self, start: Event<'static>) -> Result<Reservation, <E as Eat>::Error> {
+        self.refuse_non_str_key()?;
+        self.fits(self.open_len(self.level, start.len()))?;
+        self.event(start)?;
+        let closing = self.closing_len(self.level);
+        let slot = self.slot_len(self.level);
+        Ok(match self.limit.as_deref_mut() {
+            Some(limit) => {
+                limit.reserved += closing;
+                Reservation {
+                    closing,
+                    outer_slot: core::mem::replace(&mut limit.slot, slot),
+                }
+            }
+            None => Reservation::default(),
+        })
+    }
+
+    /// Close a container, and the variant it is the data of, if any
+    fn end(
+        &mut self,
+        arg: Event<'static>,
+        empty: bool,
+        reservation: Reservation,
+        variant: Option<Reservation>,
+    ) -> Result<(), <E as Eat>::Error> {
+        if let Some(limit) = self.limit.as_deref_mut() {
+            limit.reserved -= reservation.closing;
+            limit.slot = reservation.outer_slot;
+        }
+        if !empty {
+            self.indent(false)?;
+        }
+        self.event(arg)?;
+        match variant {
+            Some(variant) => self.close_variant(variant),
+            None => Ok(()),
+        }
+    }
+
+    fn trailing_comma(&self) -> bool {
+        self.multiline && self.schema != Schema::JsonCompatible
+    }
+
+    fn refuse_non_str_key(&self) -> Result<(), <E as Eat>::Error> {
+        match self.key && (self.schema.is_json() || self.strict) {
+            true => Err(E::error(ErrorKind::Unsupported, "key must be a string")),
+            false => Ok(()),
+        }
+    }
+
+    /// Numbers and bools, which need to be quoted when used as keys in JSON
+    fn serialize_scalar(mut self, v: &str, token: Token) -> Result<(), <E as Eat>::Error> {
+        if self.key && !self.schema.is_json() {
+            self.refuse_non_str_key()?;
+        }
+        let token = match self.key {
+            true => Token::Key,
+            false => token,
+        };
+        if self.key && self.schema.is_json() {
+            self.fits(v.len() + 2)?;
+            self.token(token, ScalarStyle::Quoted, |ser| {
+                ser.eat("\"")?;
+                ser.eat(v)?;
+                ser.eat("\"")
+            })
+        } else {
+            self.token(token, ScalarStyle::Plain, |ser| ser.eat(v))
+        }
+    }
+
+    /// Whatever `f` writes, as one [Token]
+    fn token(
+        &mut self,
+        token: Token,
+        style: ScalarStyle,
+        f: impl FnOnce(&mut Self) -> Result<(), <E as Eat>::Error>,
+    ) -> Result<(), <E as Eat>::Error> {
+        if !self.glut.wants_events() {
+            return f(self);
         }
+        self.glut.event(Event::Begin { token, style })?;
+        let res = f(self);
+        // Even after errors, so the output stays balanced when truncating
+        let end = self.glut.event(Event::End(token));
+        res.and(end)
     }
 
-    fn start(&mut self, start: &str) -> Result<(), <E as Eat>::Error> {
-        self.eat(start)?;
-        Ok(())
-    }
-
-    fn end(mut self, arg: &str, empty: bool) -> Result<(), <E as Eat>::Error> {
-        if !empty {
-            self.indent(false)?;
+    /// `value` one level deeper, after `enter` writes its [Event::Enter] if the sink [wants events][Eat::wants_events]
+    fn node<T: ?Sized + Serialize>(
+        &mut self,
+        value: &T,
+        enter: impl FnOnce(&mut E) -> Result<(), <E as Eat>::Error>,
+    ) -> Result<(), <E as Eat>::Error> {
+        if !self.glut.wants_events() || self.key {
+            return value.serialize(self.next_level());
         }
-        self.eat(arg)?;
-        Ok(())
+        enter(self.glut)?;
+        let res = value.serialize(self.next_level());
+        // Even after errors, like in [CurlySerializer::token]
+        let leave = self.glut.event(Event::Leave);
+        res.and(leave)
     }
 
     fn serialize_float(
-        mut self,
+        self,
         is_nan: bool,
         infinity: bool,
         neg_infinity: bool,
         v: impl Fn(&mut ryu::Buffer) -> &str,
     ) -> Result<(), <E as Eat>::Error> {
+        if self.schema == Schema::JsonCompatible && (is_nan || infinity || neg_infinity) {
+            return self.serialize_unit();
+        }
+        if self.strict && !self.schema.is_json() && (is_nan || infinity || neg_infinity) {
+            return Err(E::error(
+                ErrorKind::Unsupported,
+                "strict: NaN and infinite floats are not allowed",
+            ));
+        }
+        let json5 = self.schema == Schema::Json5;
         let mut buf = ryu::Buffer::new();
         let s = match (is_nan, infinity, neg_infinity) {
+            (true, false, false) if json5 => "NaN",
+            (false, true, false) if json5 => "Infinity",
+            (false, false, true) if json5 => "-Infinity",
             (true, false, false) => ".nan",
             (false, true, false) => ".inf",
             (false, false, true) => "-.inf",
             (false, false, false) => v(&mut buf),
             _ => unreachable!(),
         };
-        self.eat(s)
+        self.serialize_scalar(s, Token::Num)
     }
 
+    /// Serialize `value` on a single line if that takes at most `max_len` bytes, returns whether it did
+    ///
+    /// The trial run goes to a buffer on the stack, so `max_len` must not exceed [SHORT_MAX].
+    /// For keys, pass `widths` to have the run go on for twice as long and record what it learns about nested keys.
+    /// If the output is a single [Token], pass its kind in `token`,
+    /// otherwise sinks that [want events][Eat::wants_events] get it serialized a second time.
+    /// Not inlined so the buffer doesn't sit in the stack frame of every nesting level.
+    #[inline(never)]
     fn serialize_short<T: Serialize + ?Sized>(
         &mut self,
-        key: &T,
+        value: &T,
         max_len: usize,
-    ) -> Option<String> {
-        let max_len = match self.max_output {
-            Some(&mut max_output) if max_output < max_len && !self.multiline => return None,
-            Some(&mut max_output) if max_output < max_len => max_output,
-            _ => max_len,
+        widths: Option<&mut KeyWidths>,
+        token: Option<Token>,
+    ) -> Result<bool, <E as Eat>::Error> {
+        debug_assert!(self.max_output.is_none(), "trial runs don't nest");
+        let mut max_short_output = match widths {
+            Some(_) => 2 * max_len,
+            None => max_len,
         };
-        let mut max_short_output = max_len;
-        let mut short = String::with_capacity(max_len);
-        let res = key.serialize(CurlySerializer {
+        debug_assert!(max_short_output <= SHORT_MAX);
+        let mut short = StackStr::<SHORT_MAX>::new();
+        let res = value.serialize(CurlySerializer {
             glut: &mut short,
             multiline: false,
+            schema: self.schema,
+            strict: self.strict,
+            limit: None,
+            max_depth: self.max_depth,
+            max_elements: self.max_elements,
+            key: self.key,
             level: self.level,
             max_output: Some(&mut max_short_output),
+            widths,
         });
-        let res = res.is_ok().then_some(short)?;
-        assert!(res.len() <= max_len);
-        Some(res)
+        if res.is_err() || short.len > max_len {
+            return Ok(false);
+        }
+        match token {
+            _ if !self.glut.wants_events() => self.eat(short.as_str())?,
+            Some(token) => {
+                let style = match short.as_str().starts_with('"') {
+                    true => ScalarStyle::Quoted,
+                    false => ScalarStyle::Plain,
+                };
+                self.token(token, style, |ser| ser.eat(short.as_str()))?
+            }
+            None => {
+                // Known to fit, so keys nested in it go on the same line, as in a trial run
+                let mut max_short_output = usize::MAX;
+                value.serialize(CurlySerializer {
+                    glut: &mut *self.glut,
+                    multiline: false,
+                    schema: self.schema,
+                    strict: self.strict,
+                    limit: self.limit.as_deref_mut(),
+                    max_depth: self.max_depth,
+                    max_elements: self.max_elements,
+                    key: self.key,
+                    level: self.level,
+                    max_output: Some(&mut max_short_output),
+                    widths: None,
+                })?;
+            }
+        }
+        Ok(true)
+    }
+
+    /// Serialize a key nested in a trial run, noting in [KeyWidths] if it's too long
+    fn trial_key<T: Serialize + ?Sized>(&mut self, key: &T) -> Result<(), <E as Eat>::Error> {
+        let remaining = |ser: &Self| ser.max_output.as_deref().copied().unwrap_or(0);
+        let start = remaining(self);
+        let index = self.widths.as_deref_mut().map(KeyWidths::next);
+        let res = key.serialize(self.next_level());
+        let end = remaining(self);
+        if let (Some(widths), Some(index)) = (self.widths.as_deref_mut(), index) {
+            // Until the trial run is halfway through, there's enough space left to tell
+            let long = match res {
+                Ok(()) => start - end > widths.short,
+                Err(_) => start >= widths.short,
+            };
+            if long {
+                widths.set_long(index);
+            }
+        }
+        res
+    }
+
+    /// Double-quoted string spread over lines of about 80 characters
+    fn serialize_multiline_str(&mut self, v: &str) -> Result<(), <E as Eat>::Error> {
+        self.eat_quote(true)?;
+        let mut chars_on_line = usize::MAX;
+        let mut toks = WordOrSpace(v).peekable();
+        while let Some(tok) = toks.next() {
+            match tok {
+                " " => {
+                    // Newline "early" if the next word wouldn't fit onto the current line - but only up to some sensible length
+                    // Working with length here is a crude approximation
+                    let next_len = toks
+                        .peek()
+                        .map(|s| s.len())
+                        .filter(|&l| l < 60)
+                        .unwrap_or(0);
+                    if chars_on_line.saturating_add(next_len) >= 80 {
+                        assert!(self.multiline);
+                        chars_on_line = 0;
+                        self.indent(false)?;
+                        match toks.peek() {
+                            Some(&" ") => {
+                                self.eat(" \\ ")?;
+                                toks.next();
+                            }
+                            _ => self.eat("  ")?,
+                        }
+                    } else {
+                        match toks.peek() {
+                            Some(&" ") => {
+                                self.eat(" ")?;
+                                while toks.peek() == Some(&" ") {
+                                    self.eat(" ")?;
+                                    if chars_on_line >= 80 {
+                                        self.eat("\\")?;
+                                        break;
+                                    }
+                                    chars_on_line += 1;
+                                    toks.next();
+                                }
+                            }
+                            Some(_) => self.eat(tok)?,
+                            None => self.indent(false)?,
+                        }
+                    }
+                }
+                "\n" => {
+                    self.eat("\\n")?;
+                    chars_on_line = usize::MAX;
+                    match toks.peek() {
+                        Some(&"\n") => {
+                            self.eat("\\")?;
+                            self.indent(true)?
+                        }
+                        None => {
+                            self.eat("\\")?;
+                            self.indent(false)?
+                        }
+                        _ => (),
+                    }
+                }
+                a => {
+                    let mut run = 0;
+                    for (i, c) in a.char_indices() {
+                        if chars_on_line >= 80 {
+                            self.eat(&a[run..i])?;
+                            run = i;
+                            self.eat("\\")?;
+                            self.indent(true)?;
+                            chars_on_line = 0;
+                        }
+                        assert!(c != ' ');
+                        if !is_verbatim_in_string(c) {
+                            self.eat(&a[run..i])?;
+                            self.serialize_char_in_string(c)?;
+                            run = i + c.len_utf8();
+                        }
+                        chars_on_line += 1;
+                    }
+                    self.eat(&a[run..])?;
+                    if toks.peek().is_none() {
+                        self.eat("\\")?;
+                        self.indent(false)?;
+                    }
+                }
+            }
+        }
+        self.eat_quote(false)?;
+        Ok(())
+    }
+
+    /// Double-quoted string on a single line
+    fn serialize_quoted_str(&mut self, v: &str) -> Result<(), <E as Eat>::Error> {
+        self.eat_quote(true)?;
+        self.eat_escaped(v)?;
+        self.eat_quote(false)
+    }
+
+    /// `v` escaped for the inside of a quoted string
+    fn eat_escaped(&mut self, v: &str) -> Result<(), <E as Eat>::Error> {
+        let mut run = 0;
+        for (i, c) in v.char_indices() {
+            if !is_verbatim_in_string(c) {
+                self.eat(&v[run..i])?;
+                self.serialize_char_in_string(c)?;
+                run = i + c.len_utf8();
+            }
+        }
+        self.eat(&v[run..])
     }
 
     fn serialize_char_in_string(&mut self, c: char) -> Result<(), <E as Eat>::Error> {
         match c {
+            '\0' if self.schema.is_json() => self.eat("\\u0000")?,
             '\0' => self.eat("\\0")?,
             '\\' => self.eat("\\\\")?,
             '"' => self.eat("\\\"")?,
@@ -630,40 +1852,464 @@ impl<'e, E: Eat> CurlySerializer<'e, E> {
                 self.eat(c.encode_utf8(&mut [0u8; 4]))?;
             }
             c => match &*c.encode_utf16(&mut [0u16; 2]) {
-                &[tb] => {
-                    self.eat("\\u")?;
-                    self.eat(&format!("{:04x}", tb))?;
-                }
-                _ => {
-                    self.eat("\\U")?;
-                    self.eat(&format!("{:08x}", c as u32))?;
+                &[tb] => self.eat_hex_escape(b'u', tb.into(), 4)?,
+                _ if self.schema.is_json() => {
+                    // JSON only knows surrogate pair escapes, which libyaml refuses, so write it raw
+                    self.eat(c.encode_utf8(&mut [0u8; 4]))?;
                 }
+                _ => self.eat_hex_escape(b'U', c.into(), 8)?,
             },
         };
         Ok(())
     }
 
+    /// `\\u` or `\\U` escapes, in one piece
+    fn eat_hex_escape(&mut self, kind: u8, v: u32, digits: usize) -> Result<(), <E as Eat>::Error> {
+        let mut buf = [b'\\', kind, 0, 0, 0, 0, 0, 0, 0, 0];
+        for (i, b) in buf[2..2 + digits].iter_mut().rev().enumerate() {
+            *b = b"0123456789abcdef"[(v >> (4 * i)) as usize & 0xf];
+        }
+        self.eat(core::str::from_utf8(&buf[..2 + digits]).expect("ASCII"))
+    }
+
     fn eat(&mut self, v: &str) -> Result<(), <E as Eat>::Error> {
+        match v.is_empty() {
+            true => Ok(()),
+            false => self.event(Event::Text(v)),
+        }
+    }
+
+    /// Output `event`, taking its text from the budget of a trial run and the [Limit]
+    #[inline(always)]
+    fn event(&mut self, event: Event<'_>) -> Result<(), <E as Eat>::Error> {
+        if self.max_output.is_some() || self.limit.is_some() {
+            let last = match event {
+                Event::Text(v) => v.as_bytes().last().copied().unwrap_or(b' '),
+                _ => b' ',
+            };
+            self.count(event.len(), last)?;
+        }
+        match self.glut.wants_events() {
+            true => self.glut.event(event),
+            false => event.write_to(self.glut),
+        }
+    }
+
+    /// Take `len` bytes ending in `last` from the budget of a trial run and the [Limit]
+    fn count(&mut self, len: usize, last: u8) -> Result<(), <E as Eat>::Error> {
         if let Some(max_len) = self.max_output.as_mut() {
-            if v.len() > **max_len {
-                return Err(ser::Error::custom("internal: length exceeded"));
+            if len > **max_len {
+                return Err(E::error(ErrorKind::Limit, "internal: length exceeded"));
+            }
+            **max_len = max_len.saturating_sub(len);
+        }
+        if let Some(limit) = self.limit.as_deref() {
+            if !limit.truncate && !limit.cut && len > limit.remaining {
+                return Err(E::error(ErrorKind::Limit, "output longer than the limit"));
+            }
+            self.fits(len)?;
+            let limit = self.limit.as_deref_mut().expect("checked");
+            limit.remaining = limit.remaining.saturating_sub(len);
+            limit.last = last;
+        }
+        Ok(())
+    }
+
+    /// Check that `len` more bytes fit into a truncating [Limit], if there is one
+    ///
+    /// If not, this is where the output gets cut. Only things that are reserved can be written after that.
+    fn fits(&mut self, len: usize) -> Result<(), <E as Eat>::Error> {
+        let limit = match self.limit.as_deref_mut() {
+            Some(limit) if limit.truncate && !limit.cut => limit,
+            _ => return Ok(()),
+        };
+        if len + limit.reserved + limit.slot <= limit.remaining {
+            return Ok(());
+        }
+        limit.cut = true;
+        if limit.in_string {
+            limit.in_string = false;
+            limit.marked = true;
+            // A lone backslash at the end would escape the marker
+            let close = match limit.last {
+                b'\\' if !self.schema.is_json() => "\n…\"",
+                _ => "…\"",
+            };
+            self.eat(close)?;
+        } else if limit.reserved == 0 {
+            // Nothing open that would mark the cut, so the marker is all the output there is
+            let marker_len = match self.schema.is_json() {
+                true => "\"…\"".len(),
+                false => "…".len(),
+            };
+            if marker_len > limit.remaining {
+                limit.cut = false;
+                return Err(E::error(ErrorKind::Limit, "output longer than the limit"));
+            }
+            self.eat_marker(false)?;
+        }
+        Err(E::error(ErrorKind::Limit, "output truncated at the limit"))
+    }
+
+    /// Opening or closing quote of a string, so a [Limit] can close it when cutting the output
+    fn eat_quote(&mut self, open: bool) -> Result<(), <E as Eat>::Error> {
+        self.eat("\"")?;
+        if let Some(limit) = self.limit.as_deref_mut() {
+            limit.in_string = open;
+        }
+        Ok(())
+    }
+
+    /// The `…` left where the output was cut, as a key without value or as a value
+    fn eat_marker(&mut self, key: bool) -> Result<(), <E as Eat>::Error> {
+        match self.limit.as_deref_mut() {
+            Some(limit) if !limit.marked => limit.marked = true,
+            _ => return Ok(()),
+        }
+        match (self.schema.is_json(), key) {
+            (false, _) => self.eat("…"),
+            (true, false) => self.eat("\"…\""),
+            (true, true) => {
+                self.eat("\"…\"")?;
+                self.event(Event::Colon)?;
+                self.eat("null")
+            }
+        }
+    }
+
+    fn indent_len(&self, extra: bool) -> usize {
+        match self.multiline {
+            true => 1 + 2 * (self.level + extra as usize),
+            false => 1,
+        }
+    }
+
+    /// Upper bound for what [CurlySerializer::end] writes for the container itself
+    fn closing_len(&self, level: usize) -> usize {
+        match self.multiline {
+            true => 1 + 2 * level + 1,
+            false => 1 + 1,
+        }
+    }
+
+    /// Space for a marker in a container at `level`, with separator and indentation, or `: ` in front
+    fn slot_len(&self, level: usize) -> usize {
+        let indent = match self.multiline {
+            true => 1 + 2 * (level + 1),
+            false => 1,
+        };
+        1 + indent + MARKER_MAX
+    }
+
+    /// Space that opening a container at `level` needs, including what it reserves
+    fn open_len(&self, level: usize, start_len: usize) -> usize {
+        let outer_slot = self.limit.as_ref().map_or(0, |limit| limit.slot);
+        start_len + self.closing_len(level) + self.slot_len(level).saturating_sub(outer_slot)
+    }
+
+    fn truncated(&self) -> bool {
+        self.limit.as_ref().map_or(false, |limit| limit.cut)
+    }
+
+    /// Whether a container at this level leaves out its next element, after `shown` of them
+    fn elides(&self, shown: usize) -> bool {
+        self.max_depth.map_or(false, |depth| self.level >= depth)
+            || self.max_elements.map_or(false, |max| shown >= max)
+    }
+
+    /// `…3 more`, in place of elements left out by [CurlySerializer::max_depth] or [CurlySerializer::max_elements]
+    fn eat_elided(&mut self, elided: usize, key: bool) -> Result<(), <E as Eat>::Error> {
+        let mut buf = itoa::Buffer::new();
+        let num = buf.format(elided);
+        let json = self.schema.is_json();
+        self.fits(num.len() + 8 + json as usize * 2 + (json && key) as usize * 6)?;
+        if json {
+            self.eat("\"")?;
+        }
+        self.eat("…")?;
+        self.eat(num)?;
+        self.eat(" more")?;
+        if json {
+            self.eat("\"")?;
+            if key {
+                self.event(Event::Colon)?;
+                self.eat("null")?;
+            }
+        }
+        Ok(())
+    }
+
+    /// After the output was cut in an open container at `at`: mark the spot if that hasn't happened yet
+    fn eat_cut_marker(&mut self, at: Cut, map: bool) -> Result<(), <E as Eat>::Error> {
+        let marked = self.limit.as_ref().map_or(true, |limit| limit.marked);
+        match at {
+            Cut::Next { comma } if !marked => {
+                if comma {
+                    self.event(Event::Comma)?;
+                }
+                self.indent(true)?;
+                self.eat_marker(map)
+            }
+            Cut::Key if !marked => self.eat_marker(true),
+            Cut::AfterKey if !marked => {
+                self.event(Event::Colon)?;
+                self.eat_marker(false)
             }
-            **max_len = max_len.saturating_sub(v.len());
+            Cut::AfterKey if self.schema.is_json() => {
+                self.event(Event::Colon)?;
+                self.eat("null")
+            }
+            Cut::Value => self.eat_marker(false),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// What a trial run of a key found out about the keys nested in it
+///
+/// A key too long for one line is spread over several, and each key nested in it
+/// needs to find out whether it fits on one line in turn.
+/// Trying each of them separately takes quadratic time on deeply nested keys,
+/// so key trial runs go on for twice the allowed length, and note which nested keys they saw ending too late.
+/// Any nested key that starts in the first half and doesn't end in the run is too long, too.
+struct KeyWidths {
+    /// Longest key that fits on one line
+    short: usize,
+    /// Keys seen so far, numbering them in the same order during the trial run and the actual output
+    count: usize,
+    /// One bit per key, set if it is known not to fit
+    long: [u64; 4],
+}
+
+impl KeyWidths {
+    fn new(short: usize) -> Self {
+        KeyWidths {
+            short,
+            count: 0,
+            long: [0; 4],
+        }
+    }
+
+    fn next(&mut self) -> usize {
+        self.count += 1;
+        self.count - 1
+    }
+
+    fn next_is_long(&mut self) -> bool {
+        let i = self.next();
+        self.long
+            .get(i / 64)
+            .map_or(false, |bits| bits & 1 << (i % 64) != 0)
+    }
+
+    fn set_long(&mut self, i: usize) {
+        if let Some(bits) = self.long.get_mut(i / 64) {
+            *bits |= 1 << (i % 64);
+        }
+    }
+}
+
+/// Cap on the output length, see [CurlySerializer::limit]
+///
+/// Once the output would get longer than the limit, serialization fails.
+/// With `truncate`, the output instead ends early, with a `…` marker where it was cut,
+/// and all open strings and brackets closed.
+/// Serialization then still returns an error, [Limit::truncated] tells it apart from other errors.
+/// Output cut before anything was opened is only the marker, `…` or `"…"` in JSON,
+/// so `max_len` must be at least 3 or 5 bytes, or serialization fails without truncating.
+#[derive(Debug, Clone)]
+pub struct Limit {
+    remaining: usize,
+    /// End the output early instead of failing
+    pub truncate: bool,
+    /// Space for closing all open brackets
+    reserved: usize,
+    /// Space for the marker in the innermost open container
+    slot: usize,
+    in_string: bool,
+    /// Last byte written
+    last: u8,
+    cut: bool,
+    marked: bool,
+}
+
+impl Limit {
+    /// Fail once the output gets longer than `max_len` bytes
+    pub fn new(max_len: usize) -> Self {
+        Limit {
+            remaining: max_len,
+            truncate: false,
+            reserved: 0,
+            slot: MARKER_MAX,
+            in_string: false,
+            last: 0,
+            cut: false,
+            marked: false,
+        }
+    }
+
+    /// Cut the output at `max_len` bytes instead of failing
+    pub fn truncate(max_len: usize) -> Self {
+        Limit {
+            truncate: true,
+            ..Limit::new(max_len)
+        }
+    }
+
+    /// Whether the output was cut
+    pub fn truncated(&self) -> bool {
+        self.cut
+    }
+}
+
+/// Longest marker: `"…": null`
+const MARKER_MAX: usize = 11;
+
+/// What a container added to [Limit]'s reservations
+#[derive(Clone, Copy, Default)]
+struct Reservation {
+    closing: usize,
+    outer_slot: usize,
+}
+
+/// Where the output was cut inside a container, deciding what goes around the `…` marker
+#[derive(Clone, Copy)]
+enum Cut {
+    /// Before the next element or key, and the `,` separating it if `comma`
+    Next { comma: bool },
+    /// Before a key, after separator and indentation
+    Key,
+    /// In or after a key
+    AfterKey,
+    /// In a value
+    Value,
+}
+
+/// Longest output [CurlySerializer::serialize_short] can try
+const SHORT_MAX: usize = 1024;
+
+/// Longest output of [Serializer::collect_str] formatted on the stack
+///
+/// Anything longer is written as a quoted string while it is formatted, see [Escape].
+const COLLECT_MAX: usize = SHORT_MAX;
+
+/// Fixed-capacity string, for formatting without allocating
+struct StackStr<const N: usize> {
+    buf: [u8; N],
+    len: usize,
+}
+
+impl<const N: usize> StackStr<N> {
+    fn new() -> Self {
+        StackStr {
+            buf: [0; N],
+            len: 0,
         }
-        self.glut.eat(v)?;
+    }
+
+    fn as_str(&self) -> &str {
+        core::str::from_utf8(&self.buf[..self.len]).expect("only whole strs are written")
+    }
+}
+
+impl<const N: usize> Eat for StackStr<N> {
+    type Error = core::fmt::Error;
+
+    fn eat(&mut self, data: &str) -> Result<(), Self::Error> {
+        core::fmt::Write::write_str(self, data)
+    }
+}
+
+impl<const N: usize> core::fmt::Write for StackStr<N> {
+    fn write_str(&mut self, s: &str) -> core::fmt::Result {
+        let dest = self
+            .buf
+            .get_mut(self.len..self.len + s.len())
+            .ok_or(core::fmt::Error)?;
+        dest.copy_from_slice(s.as_bytes());
+        self.len += s.len();
         Ok(())
     }
 }
 
+/// Writes what is formatted into it escaped, for the inside of a quoted string
+struct Escape<'s, 'e, E: Eat> {
+    ser: &'s mut CurlySerializer<'e, E>,
+    /// What made writing fail, [core::fmt::Error] can't carry it
+    error: Option<E::Error>,
+}
+
+impl<E: Eat> core::fmt::Write for Escape<'_, '_, E> {
+    fn write_str(&mut self, s: &str) -> core::fmt::Result {
+        self.ser.eat_escaped(s).map_err(|e| {
+            self.error = Some(e);
+            core::fmt::Error
+        })
+    }
+}
+
 #[doc(hidden)]
 pub struct CurlySeq<'a, E> {
     first: bool,
+    shown: usize,
+    elided: usize,
+    variant: Option<Reservation>,
+    reservation: Reservation,
     ser: CurlySerializer<'a, E>,
 }
 impl<'e, E: Eat> CurlySeq<'e, E> {
-    fn new(mut ser: CurlySerializer<'e, E>) -> Result<Self, <E as Eat>::Error> {
-        CurlySerializer::start(&mut ser, "[")?;
-        Ok(CurlySeq { first: true, ser })
+    fn new(
+        mut ser: CurlySerializer<'e, E>,
+        variant: Option<Reservation>,
+    ) -> Result<Self, <E as Eat>::Error> {
+        let reservation = ser.start(Event::SeqStart)?;
+        // What's left of a sequence that's nested too deep fits on one line
+        ser.multiline &= !ser.elides(0);
+        Ok(CurlySeq {
+            first: true,
+            shown: 0,
+            elided: 0,
+            variant,
+            reservation,
+            ser,
+        })
+    }
+
+    /// Separator, indentation, and whatever `f` writes as the element
+    fn element(
+        &mut self,
+        f: impl FnOnce(&mut CurlySerializer<'e, E>) -> Result<(), <E as Eat>::Error>,
+    ) -> Result<(), <E as Eat>::Error> {
+        let comma = !(self.ser.trailing_comma() || self.first);
+        if let Err(e) = self.ser.fits(comma as usize + self.ser.indent_len(true)) {
+            return self.cut(e, Cut::Next { comma });
+        }
+        self.first = false;
+        if comma {
+            self.ser.event(Event::Comma)?;
+        }
+        self.ser.indent(true)?;
+        if let Err(e) = f(&mut self.ser) {
+            return self.cut(e, Cut::Value);
+        }
+        if self.ser.trailing_comma() {
+            if let Err(e) = self.ser.event(Event::Comma) {
+                return self.cut(e, Cut::Next { comma: true });
+            }
+        }
+        Ok(())
+    }
+
+    /// Pass on `e`, but if it's from truncating the output, close the sequence first
+    fn cut(&mut self, e: <E as Eat>::Error, at: Cut) -> Result<(), <E as Eat>::Error> {
+        if self.ser.truncated() {
+            self.first &= !matches!(at, Cut::Next { .. });
+            self.ser.eat_cut_marker(at, false)?;
+            let (first, variant) = (self.first, self.variant);
+            self.ser
+                .end(Event::SeqEnd, first, self.reservation, variant)?;
+        }
+        Err(e)
     }
 }
 impl<E: Eat> SerializeSeq for CurlySeq<'_, E> {
@@ -671,24 +2317,28 @@ impl<E: Eat> SerializeSeq for CurlySeq<'_, E> {
 
     type Error = <E as Eat>::Error;
 
-    fn serialize_element<T: ?Sized>(&mut self, value: &T) -> Result<(), Self::Error>
-    where
-        T: serde::Serialize,
-    {
-        match self.ser.multiline || self.first {
-            true => self.first = false,
-            false => self.ser.eat(",")?,
-        }
-        self.ser.indent(true)?;
-        value.serialize(self.ser.next_level())?;
-        if self.ser.multiline {
-            self.ser.eat(",")?;
+    fn serialize_element<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<(), Self::Error> {
+        if self.ser.elides(self.shown) {
+            self.elided += 1;
+            return Ok(());
         }
-        Ok(())
+        let index = self.shown;
+        self.shown += 1;
+        self.element(|ser| {
+            ser.node(value, |glut| {
+                glut.event(Event::Enter(Segment::Index(index)))
+            })
+        })
+        .map_err(|e| E::locate(e, Segment::Index(index)))
     }
 
-    fn end(self) -> Result<Self::Ok, Self::Error> {
-        CurlySerializer::end(self.ser, "]", self.first)
+    fn end(mut self) -> Result<Self::Ok, Self::Error> {
+        let elided = self.elided;
+        if elided > 0 {
+            self.element(|ser| ser.eat_elided(elided, false))?;
+        }
+        self.ser
+            .end(Event::SeqEnd, self.first, self.reservation, self.variant)
     }
 }
 
@@ -701,17 +2351,42 @@ enum MapNext {
 pub struct CurlyMap<'e, E> {
     next: MapNext,
     first: bool,
+    shown: usize,
+    elided: usize,
+    variant: Option<Reservation>,
+    reservation: Reservation,
     ser: CurlySerializer<'e, E>,
 }
 impl<'e, E: Eat> CurlyMap<'e, E> {
-    fn new(mut ser: CurlySerializer<'e, E>) -> Result<Self, <E as Eat>::Error> {
-        CurlySerializer::start(&mut ser, "{")?;
+    fn new(
+        mut ser: CurlySerializer<'e, E>,
+        variant: Option<Reservation>,
+    ) -> Result<Self, <E as Eat>::Error> {
+        let reservation = ser.start(Event::MapStart)?;
+        ser.multiline &= !ser.elides(0);
         Ok(CurlyMap {
             first: true,
+            shown: 0,
+            elided: 0,
+            variant,
+            reservation,
             next: MapNext::Key,
             ser,
         })
     }
+
+    /// Pass on `e`, but if it's from truncating the output, close the map first
+    fn cut(&mut self, e: <E as Eat>::Error, at: Cut) -> Result<(), <E as Eat>::Error> {
+        if self.ser.truncated() {
+            self.first &= !matches!(at, Cut::Next { .. });
+            self.ser.eat_cut_marker(at, true)?;
+            let (first, variant) = (self.first, self.variant);
+            self.ser
+                .end(Event::MapEnd, first, self.reservation, variant)?;
+        }
+        Err(e)
+    }
+
     fn next(&mut self, next: MapNext) -> Result<(), <E as Eat>::Error> {
         use MapNext::*;
         match (self.next, next) {
@@ -732,57 +2407,167 @@ impl<E: Eat> SerializeMap for CurlyMap<'_, E> {
 
     type Error = <E as Eat>::Error;
 
-    fn serialize_key<T: ?Sized>(&mut self, key: &T) -> Result<(), Self::Error>
-    where
-        T: serde::Serialize,
-    {
+    fn serialize_entry<K: ?Sized + Serialize, V: ?Sized + Serialize>(
+        &mut self,
+        key: &K,
+        value: &V,
+    ) -> Result<(), Self::Error> {
+        self.serialize_key(key)?;
+        let schema = self.ser.schema;
+        self.value(value, |glut| {
+            with_key(schema, key, |at| glut.event(Event::Enter(at)))
+        })
+        .map_err(|e| self.locate(e, key))
+    }
+
+    fn serialize_key<T: ?Sized + Serialize>(&mut self, key: &T) -> Result<(), Self::Error> {
         self.next(MapNext::Key)?;
-        match self.ser.multiline || self.first {
-            true => self.first = false,
-            false => self.ser.eat(",")?,
+        if self.ser.elides(self.shown) {
+            self.elided += 1;
+            return Ok(());
+        }
+        self.shown += 1;
+        self.entry(|map| map.key(key))
+    }
+
+    fn serialize_value<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<(), Self::Error> {
+        // Without the key at hand, like when it can't be written on one line
+        self.value(value, |glut| glut.event(Event::Enter(Segment::Key("…"))))
+    }
+
+    fn end(mut self) -> Result<Self::Ok, Self::Error> {
+        let elided = self.elided;
+        if elided > 0 {
+            self.entry(|map| map.ser.eat_elided(elided, true))?;
+            if self.ser.trailing_comma() {
+                if let Err(e) = self.ser.event(Event::Comma) {
+                    return self.cut(e, Cut::Next { comma: true });
+                }
+            }
+        }
+        self.ser
+            .end(Event::MapEnd, self.first, self.reservation, self.variant)
+    }
+}
+
+impl<E: Eat> CurlyMap<'_, E> {
+    /// Pass `e` to [Eat::locate] with `key` written on one line
+    fn locate<K: ?Sized + Serialize>(&self, e: <E as Eat>::Error, key: &K) -> <E as Eat>::Error {
+        with_key(self.ser.schema, key, |at| E::locate(e, at))
+    }
+
+    /// Separator, `: `, and `value` as the entry's value, see [CurlySerializer::node]
+    fn value<T: ?Sized + Serialize>(
+        &mut self,
+        value: &T,
+        enter: impl FnOnce(&mut E) -> Result<(), <E as Eat>::Error>,
+    ) -> Result<(), <E as Eat>::Error> {
+        self.next(MapNext::Value)?;
+        if self.elided > 0 {
+            return Ok(());
+        }
+        if let Err(e) = self.ser.fits(2) {
+            return self.cut(e, Cut::AfterKey);
+        }
+        self.ser.event(Event::Colon)?;
+        if let Err(e) = self.ser.node(value, enter) {
+            return self.cut(e, Cut::Value);
+        }
+        if self.ser.trailing_comma() {
+            if let Err(e) = self.ser.event(Event::Comma) {
+                return self.cut(e, Cut::Next { comma: true });
+            }
+        }
+        Ok(())
+    }
+
+    /// Separator, indentation, and whatever `f` writes as the key
+    fn entry(
+        &mut self,
+        f: impl FnOnce(&mut Self) -> Result<(), <E as Eat>::Error>,
+    ) -> Result<(), <E as Eat>::Error> {
+        let comma = !(self.ser.trailing_comma() || self.first);
+        if let Err(e) = self.ser.fits(comma as usize + self.ser.indent_len(true)) {
+            return self.cut(e, Cut::Next { comma });
+        }
+        self.first = false;
+        if comma {
+            self.ser.event(Event::Comma)?;
         }
         self.ser.indent(true)?;
+        let remaining = self.ser.limit.as_ref().map(|limit| limit.remaining);
+        if let Err(e) = f(self) {
+            // If nothing of the key made it, the marker can take its place
+            let key_written = self.ser.limit.as_ref().map(|limit| limit.remaining) != remaining;
+            return self.cut(e, if key_written { Cut::AfterKey } else { Cut::Key });
+        }
+        Ok(())
+    }
 
+    fn key<T: ?Sized + Serialize>(&mut self, key: &T) -> Result<(), <E as Eat>::Error> {
         let shortlen = match self.ser.multiline {
             true => 80,   // On multiline,
             false => 512, // On singliline, YAML 1.1 forbids flow keys longer than 1024 without "?". Approximate.
         };
-        if let Some(singleline) = self.ser.serialize_short(key, shortlen) {
-            self.ser.eat(&singleline)?;
+        let in_key = core::mem::replace(&mut self.ser.key, true);
+        if self.ser.schema.is_json() {
+            key.serialize(self.ser.next_level())?;
+        } else if self.ser.strict {
+            let mut ser = self.ser.next_level();
+            ser.multiline = false;
+            key.serialize(ser)?;
+        } else if self.ser.max_output.is_some() {
+            // Inside a trial run: if this doesn't fit, neither does the whole run
+            self.ser.trial_key(key)?;
         } else {
-            if self.ser.multiline
-                || self
+            // No need to try keys nested in one that was tried already and ran too long
+            let try_short = !self
+                .ser
+                .widths
+                .as_deref_mut()
+                .map_or(false, KeyWidths::next_is_long);
+            let mut widths = KeyWidths::new(shortlen);
+            if !(try_short
+                && self
                     .ser
-                    .max_output
-                    .as_ref()
-                    .map_or(true, |x| **x > shortlen)
+                    .serialize_short(key, shortlen, Some(&mut widths), None)?)
             {
-                self.ser.eat("? ")?;
+                self.ser.event(Event::ComplexKey)?;
+                let mut ser = self.ser.next_level();
+                if try_short {
+                    widths.count = 0;
+                    ser.widths = Some(&mut widths);
+                }
+                key.serialize(ser)?;
+                if self.ser.multiline {
+                    self.ser.indent(true)?;
+                }
             }
-            key.serialize(self.ser.next_level())?;
-            if self.ser.multiline {
-                self.ser.indent(true)?;
+            if let (true, Some(outer)) = (try_short, self.ser.widths.as_deref_mut()) {
+                outer.count += widths.count;
             }
         }
+        self.ser.key = in_key;
         Ok(())
     }
+}
 
-    fn serialize_value<T: ?Sized>(&mut self, value: &T) -> Result<(), Self::Error>
-    where
-        T: serde::Serialize,
-    {
-        self.next(MapNext::Value)?;
-        self.ser.eat(": ")?;
-        value.serialize(self.ser.next_level())?;
-        if self.ser.multiline {
-            self.ser.eat(",")?;
-        }
-        Ok(())
-    }
-
-    fn end(self) -> Result<Self::Ok, Self::Error> {
-        CurlySerializer::end(self.ser, "}", self.first)
-    }
+/// `f` with `key` written on one line as a [Segment], or `…` if that fails
+#[inline(never)]
+fn with_key<K: ?Sized + Serialize, R>(
+    schema: Schema,
+    key: &K,
+    f: impl FnOnce(Segment<'_>) -> R,
+) -> R {
+    let mut buf = StackStr::<SHORT_MAX>::new();
+    let mut ser = CurlySerializer::new(&mut buf).oneline();
+    ser.schema = schema;
+    ser.key = true;
+    let key = match key.serialize(ser) {
+        Ok(()) => buf.as_str(),
+        Err(_) => "…",
+    };
+    f(Segment::Key(key))
 }
 
 struct WordOrSpace<'a>(&'a str);
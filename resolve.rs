@@ -0,0 +1,179 @@
+//! Implicit tag resolution, i.e. which plain scalars a YAML parser would not read back as strings
+//!
+//! Both checks err on the side of caution: they match everything the spec regexes match,
+//! and some more where that keeps the code simple (e.g. case-insensitive keywords).
+
+/// Whether a YAML 1.1 parser (PyYAML, go-yaml v2, …) may resolve `v` to something other than a string
+///
+/// See <https://yaml.org/type/>
+pub(crate) fn yaml11(v: &str) -> bool {
+    keyword(v)
+        || matches!(v, "=" | "<<")
+        || ["y", "yes", "n", "no", "on", "off"]
+            .iter()
+            .any(|kw| v.eq_ignore_ascii_case(kw))
+        || number11(v)
+        || timestamp(v)
+}
+
+/// Whether a YAML 1.2 core schema parser may resolve `v` to something other than a string
+///
+/// See <https://yaml.org/spec/1.2.2/#1032-tag-resolution>
+pub(crate) fn yaml12(v: &str) -> bool {
+    keyword(v) || number12(v)
+}
+
+/// Keywords shared by both versions, plus Rust's float parser's `inf` and `nan`
+fn keyword(v: &str) -> bool {
+    matches!(v, "" | "~")
+        || ["null", "true", "false", "nan", "inf", "infinity"]
+            .iter()
+            .any(|kw| v.eq_ignore_ascii_case(kw))
+}
+
+/// `int`, `float` and the sexagesimal forms of both from <https://yaml.org/type/>
+fn number11(v: &str) -> bool {
+    let digit_ = |c: u8| c.is_ascii_digit() || c == b'_';
+    let mut s = Scan::new(v);
+    s.sign();
+    if s.prefixed_int() {
+        return true;
+    }
+    let mut base60 = s;
+    if base60.digits(1, 1) {
+        base60.many(digit_);
+        if base60.rest.first() == Some(&b':') {
+            while base60.byte(b':') {
+                if !base60.digits(1, 2) {
+                    return false;
+                }
+            }
+            if base60.byte(b'.') {
+                base60.many(digit_);
+            }
+            return base60.done();
+        }
+    }
+    if s.many(digit_) > 0 && s.done() {
+        return true;
+    }
+    if !s.byte(b'.') {
+        return false;
+    }
+    if s.special_float() {
+        return true;
+    }
+    s.many(|c| digit_(c) || c == b'.');
+    s.exponent();
+    s.done()
+}
+
+/// `int` and `float` from the core schema, plus the `0b` and signed hex/octal forms serde_yaml accepts
+fn number12(v: &str) -> bool {
+    let mut s = Scan::new(v);
+    s.sign();
+    if s.prefixed_int() {
+        return true;
+    }
+    let int = s.many(|c| c.is_ascii_digit());
+    if s.byte(b'.') {
+        if int == 0 && s.special_float() {
+            return true;
+        }
+        if s.many(|c| c.is_ascii_digit()) == 0 && int == 0 {
+            return false;
+        }
+    } else if int == 0 {
+        return false;
+    }
+    s.exponent();
+    s.done()
+}
+
+/// `timestamp` from <https://yaml.org/type/>, with anything accepted after the date and separator
+fn timestamp(v: &str) -> bool {
+    let mut s = Scan::new(v);
+    let date = s.digits(4, 4) && s.byte(b'-') && s.digits(1, 2) && s.byte(b'-') && s.digits(1, 2);
+    date && (s.done() || s.any(|c| matches!(c, b'T' | b't' | b' ' | b'\t')))
+}
+
+/// Minimal cursor for hand-rolled regexes
+#[derive(Clone, Copy)]
+struct Scan<'a> {
+    rest: &'a [u8],
+}
+
+impl<'a> Scan<'a> {
+    fn new(v: &'a str) -> Self {
+        Scan { rest: v.as_bytes() }
+    }
+
+    fn any(&mut self, f: impl Fn(u8) -> bool) -> bool {
+        match self.rest.split_first() {
+            Some((&c, rest)) if f(c) => {
+                self.rest = rest;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn many(&mut self, f: impl Fn(u8) -> bool) -> usize {
+        let mut n = 0;
+        while self.any(&f) {
+            n += 1;
+        }
+        n
+    }
+
+    fn byte(&mut self, b: u8) -> bool {
+        self.any(|c| c == b)
+    }
+
+    /// Between `min` and `max` ASCII digits
+    fn digits(&mut self, min: usize, max: usize) -> bool {
+        let mut n = 0;
+        while n < max && self.any(|c| c.is_ascii_digit()) {
+            n += 1;
+        }
+        n >= min
+    }
+
+    fn done(&self) -> bool {
+        self.rest.is_empty()
+    }
+
+    fn sign(&mut self) {
+        self.any(|c| c == b'+' || c == b'-');
+    }
+
+    /// `[eE][-+]?[0-9]+`, optional
+    fn exponent(&mut self) {
+        let mut s = *self;
+        if s.any(|c| c == b'e' || c == b'E') {
+            s.sign();
+            if s.many(|c| c.is_ascii_digit()) > 0 {
+                *self = s;
+            }
+        }
+    }
+
+    /// `0x…`, `0o…`, `0b…` spanning the rest of the input
+    fn prefixed_int(&self) -> bool {
+        let digits: fn(u8) -> bool = match self.rest {
+            [b'0', b'x', ..] => |c| c.is_ascii_hexdigit() || c == b'_',
+            [b'0', b'o', ..] => |c| matches!(c, b'0'..=b'7' | b'_'),
+            [b'0', b'b', ..] => |c| matches!(c, b'0' | b'1' | b'_'),
+            _ => return false,
+        };
+        let mut s = Scan {
+            rest: &self.rest[2..],
+        };
+        s.many(digits) > 0 && s.done()
+    }
+
+    /// The rest of `.inf` or `.nan`
+    fn special_float(&self) -> bool {
+        self.rest.eq_ignore_ascii_case(b"inf") || self.rest.eq_ignore_ascii_case(b"nan")
+    }
+}
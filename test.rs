@@ -1,6 +1,8 @@
 use std::println;
 
 use alloc::{
+    collections::BTreeMap,
+    format,
     string::{String, ToString},
     vec,
 };
@@ -12,15 +14,19 @@ fn trips<S: Serialize + DeserializeOwned + std::fmt::Debug + PartialEq>(s: S) {
         serde_yaml::to_string(&s).unwrap()
     );
 
-    let mut out = String::new();
+    for multiline in [true, false] {
+        let mut out = String::new();
 
-    let ser = s.serialize(super::CurlySerializer::new(&mut out));
+        let mut ser = super::CurlySerializer::new(&mut out);
+        ser.multiline = multiline;
+        let ser = s.serialize(ser);
 
-    println!("---\n# Serialized with curly_yaml\n{out}");
+        println!("---\n# Serialized with curly_yaml\n{out}");
 
-    ser.unwrap();
-    let de = serde_yaml::from_str::<S>(&out).unwrap();
-    assert_eq!(de, s);
+        ser.unwrap();
+        let de = serde_yaml::from_str::<S>(&out).unwrap();
+        assert_eq!(de, s);
+    }
 }
 
 #[test]
@@ -72,3 +78,916 @@ fn two_spaces_eaten() {
             .to_string(),
     );
 }
+
+#[test]
+fn plain_scalars() {
+    let plain = vec![
+        "foo-bar",
+        "my.host.name",
+        "/usr/bin",
+        "v1.2.3",
+        "Grüße",
+        "a#b",
+        "it's",
+        "1st",
+        "2fa",
+        ".gitignore",
+        "0xg",
+        "2001-12-14-backup",
+    ];
+    trips(
+        plain
+            .iter()
+            .map(|s| s.to_string())
+            .collect::<alloc::vec::Vec<_>>(),
+    );
+    assert_eq!(
+        super::to_string(&plain).unwrap().replace(['\n', ' '], ""),
+        format!("[{},]", plain.join(","))
+    );
+}
+
+#[test]
+fn quoted_scalars() {
+    for s in [
+        "", "-", "---", "a,b", "[a", "a]", "{a", "a}", "#a", "a: b", "a:b", "?a", "&a", "*a", "!a",
+        "|a", ">a", "'a", "\"a", "%a", "@a", "`a", "a b", "a\tb", "\u{feff}",
+    ] {
+        trips(s.to_string());
+        assert!(super::to_string(s).unwrap().starts_with('"'), "{s:?}");
+    }
+}
+
+#[test]
+fn resolvable_scalars() {
+    for s in [
+        "~",
+        "=",
+        "<<",
+        "Yes",
+        "oFf",
+        "NULL",
+        "-1",
+        "+1",
+        "1_000",
+        "1.0",
+        "1.2.3",
+        "1e5",
+        "+.5",
+        ".inf",
+        "-.Inf",
+        ".NaN",
+        "infinity",
+        "012",
+        "0o17",
+        "0x1F",
+        "-0x1F",
+        "0b101",
+        "1:30:00",
+        "-1:30.5",
+        "2001-12-14",
+        "2001-12-14t21:59:43.10-05:00",
+        "2001-12-14 21:59:43.10 -5",
+    ] {
+        trips(s.to_string());
+        assert_eq!(super::to_string(s).unwrap(), format!("\"{s}\""));
+    }
+    // Timestamps take anything after the date, which must still be escaped
+    for s in [
+        "2001-12-14 \", injected: \"x",
+        "2001-12-14 a\"b",
+        "2001-12-14t\\",
+        "2001-12-14T1\n2",
+        "2001-1-1 \x07",
+        "2001-1-1\t\x7f",
+    ] {
+        trips(s.to_string());
+        trips(vec![s.to_string(), "x".to_string()]);
+    }
+    assert_eq!(
+        super::to_string("2001-12-14 \"q\\\x07").unwrap(),
+        r#""2001-12-14 \"q\\\u0007""#
+    );
+}
+
+#[test]
+fn yaml12_core() {
+    let v = vec!["yes", "on", "2001-12-14", "true", "0o17", "1.5"];
+    let mut out = String::new();
+    let mut ser = super::CurlySerializer::new(&mut out).oneline();
+    ser.schema = super::Schema::Yaml12Core;
+    v.serialize(ser).unwrap();
+    assert_eq!(out, r#"[ yes, on, 2001-12-14, "true", "0o17", "1.5" ]"#);
+}
+
+#[test]
+fn json_compatible() {
+    #[derive(Serialize)]
+    enum E {
+        Unit,
+        New(f64),
+        Tuple(i32, i32),
+        Struct { a: char },
+    }
+    #[derive(Serialize)]
+    struct S {
+        e: vec::Vec<E>,
+        ints: BTreeMap<i32, bool>,
+        bools: BTreeMap<bool, ()>,
+        s: &'static str,
+        nan: f32,
+    }
+    let s = S {
+        e: vec![E::Unit, E::New(0.5), E::Tuple(1, 2), E::Struct { a: '\0' }],
+        ints: [(-1, true), (2, false)].into_iter().collect(),
+        bools: [(true, ())].into_iter().collect(),
+        s: "a very long string with \"quotes\", \\ backslashes \\, \u{1f980} and \u{7}, which needs to stay on one line",
+        nan: f32::NAN,
+    };
+    for multiline in [true, false] {
+        let mut out = String::new();
+        let mut ser = super::CurlySerializer::new(&mut out);
+        ser.multiline = multiline;
+        ser.schema = super::Schema::JsonCompatible;
+        s.serialize(ser).unwrap();
+        println!("{out}");
+        let json = serde_json::from_str::<serde_json::Value>(&out).unwrap();
+        assert_eq!(json, serde_json::to_value(&s).unwrap());
+        let yaml = serde_yaml::from_str::<serde_json::Value>(&out).unwrap();
+        assert_eq!(yaml, json);
+    }
+}
+
+#[test]
+fn json5() {
+    #[derive(Serialize)]
+    enum E {
+        Tuple(i32, i32),
+        Struct { a: f64 },
+    }
+    let s: BTreeMap<_, _> = [
+        ("plain_$key", vec![E::Tuple(1, 2)]),
+        ("quoted key", vec![E::Struct { a: f64::INFINITY }]),
+    ]
+    .into_iter()
+    .collect();
+    let mut out = String::new();
+    let mut ser = super::CurlySerializer::new(&mut out);
+    ser.schema = super::Schema::Json5;
+    s.serialize(ser).unwrap();
+    println!("{out}");
+    assert!(out.contains("\n  plain_$key: [\n    {\n      Tuple: ["));
+    assert!(out.contains("\n        a: Infinity,\n"));
+    let json5 = json5::from_str::<serde_json::Value>(&out).unwrap();
+    assert_eq!(json5, serde_json::to_value(&s).unwrap());
+}
+
+#[test]
+fn json_refuses_complex_keys() {
+    let mut out = String::new();
+    let mut ser = super::CurlySerializer::new(&mut out);
+    ser.schema = super::Schema::JsonCompatible;
+    let map: BTreeMap<_, _> = [(vec![1], 2)].into_iter().collect();
+    assert!(map.serialize(ser).is_err());
+}
+
+#[test]
+fn strict() {
+    #[derive(Serialize)]
+    enum E {
+        Unit,
+        New(i32),
+    }
+    fn strict<T: Serialize>(v: T) -> Result<String, super::Error> {
+        let mut out = String::new();
+        let mut ser = super::CurlySerializer::new(&mut out);
+        ser.strict = true;
+        v.serialize(ser)?;
+        Ok(out)
+    }
+    let long = "x".repeat(1000);
+    let map = |k: &str| {
+        [(k.to_string(), E::Unit)]
+            .into_iter()
+            .collect::<BTreeMap<_, _>>()
+    };
+    let ok = strict(map(&long)).unwrap();
+    assert!(ok.contains(&format!("\n  {long}: Unit,\n")), "{ok}");
+    assert_eq!(
+        serde_yaml::from_str::<BTreeMap<String, String>>(&ok).unwrap(),
+        [(long, "Unit".to_string())].into_iter().collect()
+    );
+    strict(map(&"x".repeat(1025))).unwrap_err();
+    // Bytes, not characters
+    let err = strict(map(&"ü".repeat(600))).unwrap_err();
+    assert_eq!(err.message(), "strict: key longer than 1024 bytes");
+    strict(E::New(1)).unwrap_err();
+    strict(f64::NAN).unwrap_err();
+    strict([(1, 2)].into_iter().collect::<BTreeMap<_, _>>()).unwrap_err();
+    strict([(vec!["a"], 2)].into_iter().collect::<BTreeMap<_, _>>()).unwrap_err();
+}
+
+#[test]
+fn deep_indent() {
+    let mut v = serde_yaml::Value::String("deep".to_string());
+    for _ in 0..100 {
+        v = serde_yaml::Value::Sequence(vec![v]);
+    }
+    trips(v);
+}
+
+#[test]
+fn to_writer_buffers() {
+    struct CountWrites(usize, vec::Vec<u8>);
+    impl std::io::Write for CountWrites {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0 += 1;
+            self.1.extend_from_slice(buf);
+            Ok(buf.len())
+        }
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+    let v = vec!["some \u{7} string"; 100];
+    let mut w = CountWrites(0, vec![]);
+    super::to_writer(&mut w, &v).unwrap();
+    assert_eq!(w.0, 1);
+    assert_eq!(
+        String::from_utf8(w.1).unwrap(),
+        super::to_string(&v).unwrap()
+    );
+}
+
+#[test]
+fn nested_keys() {
+    let nest = |depth, leaf: &str| {
+        let mut v = serde_yaml::Value::String(leaf.to_string());
+        for i in 0..depth {
+            v = serde_yaml::Value::Mapping([(v, i.into())].into_iter().collect());
+        }
+        v
+    };
+    let long = "a string too long to fit on a single line together with a few braces around";
+    assert_eq!(
+        super::to_string(&nest(3, long)).unwrap(),
+        r#"{
+  ? {
+    ? {
+      "a string too long to fit on a single line together with a few braces around": 0,
+    }
+    : 1,
+  }
+  : 2,
+}"#
+    );
+    // serde_yaml can read, but not write complex keys
+    for v in [nest(20, long), nest(20, "short")] {
+        for multiline in [true, false] {
+            let mut out = String::new();
+            let mut ser = super::CurlySerializer::new(&mut out);
+            ser.multiline = multiline;
+            v.serialize(ser).unwrap();
+            assert_eq!(v, serde_yaml::from_str::<serde_yaml::Value>(&out).unwrap());
+        }
+    }
+}
+
+#[test]
+fn display() {
+    let v = BTreeMap::from([("a", vec![1, 2]), ("b", vec![])]);
+    assert_eq!(format!("{}", super::display(&v)), "{ a: [ 1, 2 ], b: [] }");
+    assert_eq!(
+        format!("{:#}", super::display(&v)),
+        super::to_string(&v).unwrap()
+    );
+}
+
+#[test]
+fn bytes() {
+    let v = BTreeMap::from([("a", vec![1, 2]), ("b", vec![])]);
+    let s = super::to_string(&v).unwrap();
+    assert_eq!(super::to_vec(&v).unwrap(), s.as_bytes());
+
+    let mut buf = [0u8; 64];
+    let mut rest = &mut buf[..];
+    v.serialize(super::CurlySerializer::new(&mut rest).oneline())
+        .unwrap();
+    let len = 64 - rest.len();
+    assert_eq!(&buf[..len], b"{ a: [ 1, 2 ], b: [] }");
+
+    let mut buf = [0u8; 8];
+    let mut rest = &mut buf[..];
+    let err = v.serialize(super::CurlySerializer::new(&mut rest).oneline());
+    assert_eq!(err, Err(super::SliceError::BufferFull));
+}
+
+#[test]
+fn slice() {
+    #[derive(Serialize)]
+    enum Status {
+        Running { uptime: u32, note: &'static str },
+    }
+    let v = Status::Running {
+        uptime: 42,
+        note: "a note which is long enough that it gets broken over two lines when written in multiline mode, as any string longer than eighty bytes",
+    };
+    let mut buf = [0u8; 512];
+    let len = super::to_slice(&mut buf, &v).unwrap();
+    assert_eq!(&buf[..len], super::to_string(&v).unwrap().as_bytes());
+    assert_eq!(
+        super::to_slice(&mut buf[..len - 1], &v),
+        Err(super::SliceError::BufferFull)
+    );
+
+    // collect_str without a heap, past what fits on the stack
+    #[derive(PartialEq, Eq, PartialOrd, Ord)]
+    struct Words(usize);
+    impl std::fmt::Display for Words {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            (0..self.0).try_for_each(|_| f.write_str("a \"word\" \\ ü\n"))
+        }
+    }
+    impl Serialize for Words {
+        fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            serializer.collect_str(self)
+        }
+    }
+    for n in [1, 100] {
+        let v = BTreeMap::from([(Words(n), vec![Words(n)])]);
+        let text = Words(n).to_string();
+        let mut buf = [0u8; 8192];
+        let len = super::to_slice(&mut buf, &v).unwrap();
+        let out = std::str::from_utf8(&buf[..len]).unwrap();
+        assert_eq!(out, super::to_string(&v).unwrap());
+        let de: BTreeMap<String, vec::Vec<String>> = serde_yaml::from_str(out).unwrap();
+        assert_eq!(de, BTreeMap::from([(text.clone(), vec![text])]));
+        assert_eq!(
+            super::to_slice(&mut buf[..len - 1], &v),
+            Err(super::SliceError::BufferFull)
+        );
+    }
+}
+
+#[test]
+fn truncate() {
+    let v = BTreeMap::from([
+        ("short", vec!["a"]),
+        ("long", vec!["a string with \\ and \" in it"; 20]),
+    ]);
+    let full = super::to_string(&v).unwrap();
+    assert_eq!(
+        super::to_string_truncated(&v, full.len() + 64).unwrap(),
+        full
+    );
+    for max_len in [20, 50, 100, 200] {
+        let out = super::to_string_truncated(&v, max_len).unwrap();
+        println!("{out}");
+        assert!(out.len() <= max_len, "{out}");
+        assert!(out.contains('…'), "{out}");
+        serde_yaml::from_str::<serde_yaml::Value>(&out).unwrap();
+    }
+    assert_eq!(
+        format!("{:.50}", super::display(&v)),
+        r#"{ long: [ "a string with \\ and …" ] }"#
+    );
+    assert_eq!(
+        format!("{:.60}", super::display(&v)),
+        r#"{ long: [ "a string with \\ and \" in it", … ] }"#
+    );
+
+    #[derive(Serialize)]
+    enum E {
+        New(&'static str),
+        Tuple(i32, &'static str),
+    }
+    let e = vec![E::New("a string"), E::Tuple(1, "another string")];
+    for max_len in 30..100 {
+        let mut out = String::new();
+        let mut limit = super::Limit::truncate(max_len);
+        let mut ser = super::CurlySerializer::new(&mut out);
+        ser.schema = super::Schema::JsonCompatible;
+        ser.limit = Some(&mut limit);
+        let _ = e.serialize(ser);
+        assert!(out.len() <= max_len, "{out}");
+        serde_json::from_str::<serde_json::Value>(&out).unwrap();
+    }
+    // Too short for anything but the marker, or not even that
+    for max_len in 0..16 {
+        let mut out = String::new();
+        let mut limit = super::Limit::truncate(max_len);
+        let mut ser = super::CurlySerializer::new(&mut out);
+        ser.schema = super::Schema::JsonCompatible;
+        ser.limit = Some(&mut limit);
+        assert!(v.serialize(ser).is_err());
+        match max_len < 5 {
+            true => assert_eq!((out.as_str(), limit.truncated()), ("", false)),
+            false => assert_eq!((out.as_str(), limit.truncated()), (r#""…""#, true)),
+        }
+    }
+    assert_eq!(super::to_string_truncated(&v, 3).unwrap(), "…");
+    assert!(super::to_string_truncated(&v, 2).is_err());
+
+    let mut out = String::new();
+    let mut limit = super::Limit::new(full.len() - 1);
+    let mut ser = super::CurlySerializer::new(&mut out);
+    ser.limit = Some(&mut limit);
+    assert!(v.serialize(ser).is_err());
+    assert!(!limit.truncated());
+    let mut out = String::new();
+    let mut limit = super::Limit::new(full.len());
+    let mut ser = super::CurlySerializer::new(&mut out);
+    ser.limit = Some(&mut limit);
+    v.serialize(ser).unwrap();
+    assert_eq!(out, full);
+}
+
+#[test]
+fn preview() {
+    let v = serde_json::json!({ "a": (1..50).collect::<vec::Vec<_>>(), "b": { "c": { "d": [] } }, "e": null });
+    let mut out = String::new();
+    let mut ser = super::CurlySerializer::new(&mut out).oneline();
+    ser.max_depth = Some(2);
+    ser.max_elements = Some(2);
+    v.serialize(ser).unwrap();
+    assert_eq!(
+        out,
+        "{ a: [ 1, 2, …47 more ], b: { c: { …1 more } }, …1 more }"
+    );
+    serde_yaml::from_str::<serde_yaml::Value>(&out).unwrap();
+
+    let mut out = String::new();
+    let mut ser = super::CurlySerializer::new(&mut out);
+    ser.schema = super::Schema::JsonCompatible;
+    ser.max_depth = Some(1);
+    v.serialize(ser).unwrap();
+    assert_eq!(
+        out,
+        r#"{
+  "a": [ "…49 more" ],
+  "b": { "…1 more": null },
+  "e": null
+}"#
+    );
+}
+
+#[test]
+fn errors() {
+    struct Fails;
+    impl Serialize for Fails {
+        fn serialize<S: serde::Serializer>(&self, _: S) -> Result<S::Ok, S::Error> {
+            Err(serde::ser::Error::custom("nope"))
+        }
+    }
+    #[derive(Serialize)]
+    struct Container {
+        env: Fails,
+    }
+    #[derive(Serialize)]
+    struct Spec {
+        containers: vec::Vec<Option<Container>>,
+    }
+    let v = BTreeMap::from([(
+        "spec",
+        Spec {
+            containers: vec![None, None, Some(Container { env: Fails })],
+        },
+    )]);
+    let err = super::to_string(&v).unwrap_err();
+    assert_eq!(err.kind(), super::ErrorKind::Custom);
+    assert_eq!(err.message(), "nope");
+    assert_eq!(err.path(), "spec.containers[2].env");
+    assert_eq!(err.to_string(), "nope at spec.containers[2].env");
+
+    let v = vec![BTreeMap::from([(vec![1], 2)])];
+    let mut out = String::new();
+    let mut ser = super::CurlySerializer::new(&mut out);
+    ser.schema = super::Schema::JsonCompatible;
+    let err = v.serialize(ser).unwrap_err();
+    assert_eq!(err.kind(), super::ErrorKind::Unsupported);
+    assert_eq!(err.to_string(), "key must be a string at [0]");
+
+    struct Broken;
+    impl std::io::Write for Broken {
+        fn write(&mut self, _: &[u8]) -> std::io::Result<usize> {
+            Err(std::io::ErrorKind::BrokenPipe.into())
+        }
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+    let err = super::to_writer(Broken, &1).unwrap_err();
+    assert_eq!(err.kind(), super::ErrorKind::Io);
+    assert!(std::error::Error::source(&err).is_some());
+}
+
+#[test]
+fn ansi() {
+    #[derive(Serialize)]
+    enum E {
+        Tagged(Option<f64>),
+    }
+    let v = BTreeMap::from([("a", vec![E::Tagged(None), E::Tagged(Some(1.5))])]);
+    let mut glut = super::AnsiEat::new(String::new());
+    glut.styles.punct = "2";
+    v.serialize(super::CurlySerializer::new(&mut glut).oneline())
+        .unwrap();
+    assert_eq!(
+        glut.eat,
+        "\x1b[2m{\x1b[0m \x1b[34ma\x1b[0m\x1b[2m: \x1b[0m\x1b[2m[\x1b[0m \
+        \x1b[33m!Tagged\x1b[0m \x1b[35mnull\x1b[0m\x1b[2m,\x1b[0m \
+        \x1b[33m!Tagged\x1b[0m \x1b[36m1.5\x1b[0m \x1b[2m]\x1b[0m \x1b[2m}\x1b[0m"
+    );
+
+    // Escape codes don't count towards line widths
+    let long = vec![BTreeMap::from([("key", "some string value")]); 5];
+    let mut glut = super::AnsiEat::new(String::new());
+    long.serialize(super::CurlySerializer::new(&mut glut))
+        .unwrap();
+    let mut plain = glut.eat;
+    while let Some(start) = plain.find('\x1b') {
+        let end = start + plain[start..].find('m').unwrap();
+        plain.replace_range(start..=end, "");
+    }
+    assert_eq!(plain, super::to_string(&long).unwrap());
+}
+
+#[test]
+fn html() {
+    let v = BTreeMap::from([("<a>", vec![Some("it's \"quoted\" & more"), None])]);
+    let mut glut = super::HtmlEat(String::new());
+    v.serialize(super::CurlySerializer::new(&mut glut).oneline())
+        .unwrap();
+    assert_eq!(
+        glut.0,
+        r#"<span class="punct">{</span> <span class="key">&lt;a&gt;</span><span class="punct">: </span><span class="punct">[</span> <span class="str">&quot;it&#39;s \&quot;quoted\&quot; &amp; more&quot;</span><span class="punct">,</span> <span class="literal">null</span> <span class="punct">]</span> <span class="punct">}</span>"#
+    );
+}
+
+#[test]
+fn events() {
+    use super::{Eat, Event};
+    struct Events(vec::Vec<String>);
+    impl Eat for Events {
+        type Error = core::fmt::Error;
+
+        fn eat(&mut self, data: &str) -> Result<(), Self::Error> {
+            self.0.push(data.to_string());
+            Ok(())
+        }
+
+        fn wants_events(&self) -> bool {
+            true
+        }
+
+        fn event(&mut self, event: Event<'_>) -> Result<(), Self::Error> {
+            self.0.push(match event {
+                Event::Text(v) => v.to_string(),
+                e => format!("{e:?}"),
+            });
+            Ok(())
+        }
+    }
+    let v = BTreeMap::from([("a", vec![Some("yes"), None])]);
+    let mut glut = Events(vec![]);
+    v.serialize(super::CurlySerializer::new(&mut glut)).unwrap();
+    assert_eq!(
+        glut.0,
+        [
+            "MapStart",
+            "Newline { level: 1 }",
+            "Begin { token: Key, style: Plain }",
+            "a",
+            "End(Key)",
+            "Colon",
+            "Enter(Key(\"a\"))",
+            "SeqStart",
+            "Newline { level: 2 }",
+            "Enter(Index(0))",
+            "Begin { token: Str, style: Quoted }",
+            "\"",
+            "yes",
+            "\"",
+            "End(Str)",
+            "Leave",
+            "Comma",
+            "Newline { level: 2 }",
+            "Enter(Index(1))",
+            "Begin { token: Literal, style: Plain }",
+            "null",
+            "End(Literal)",
+            "Leave",
+            "Comma",
+            "Newline { level: 1 }",
+            "SeqEnd",
+            "Leave",
+            "Comma",
+            "Newline { level: 0 }",
+            "MapEnd",
+        ]
+    );
+
+    // Without an override, events go to eat as text
+    let mut glut = Events(vec![]);
+    Event::Newline { level: 2 }.write_to(&mut glut).unwrap();
+    Event::Colon.write_to(&mut glut).unwrap();
+    assert_eq!(glut.0, ["\n    ", ": "]);
+}
+
+#[test]
+fn spans() {
+    #[derive(Serialize)]
+    struct Spec {
+        replicas: u8,
+        containers: vec::Vec<BTreeMap<&'static str, &'static str>>,
+    }
+    let v = BTreeMap::from([(
+        "spec",
+        Spec {
+            replicas: 3,
+            containers: vec![BTreeMap::from([("image", "nginx"), ("a b", "c")])],
+        },
+    )]);
+    let (out, spans) = super::to_string_with_spans(&v).unwrap();
+    assert_eq!(out, super::to_string(&v).unwrap());
+    let found = spans
+        .iter()
+        .map(|s| {
+            let text = &out[s.start.offset..s.end.offset];
+            let ends = (text.chars().next().unwrap(), text.chars().last().unwrap());
+            let at = (s.start.line, s.start.column, s.end.line, s.end.column);
+            (s.path.as_str(), ends, at)
+        })
+        .collect::<vec::Vec<_>>();
+    assert_eq!(
+        found,
+        [
+            ("", ('{', '}'), (1, 1, 11, 2)),
+            ("spec", ('{', '}'), (2, 9, 10, 4)),
+            ("spec.replicas", ('3', '3'), (3, 15, 3, 16)),
+            ("spec.containers", ('[', ']'), (4, 17, 9, 6)),
+            ("spec.containers[0]", ('{', '}'), (5, 7, 8, 8)),
+            ("spec.containers[0].\"a b\"", ('c', 'c'), (6, 16, 6, 17)),
+            ("spec.containers[0].image", ('n', 'x'), (7, 16, 7, 21)),
+        ]
+    );
+}
+
+#[test]
+fn value() {
+    use super::Value;
+    #[derive(Serialize)]
+    enum E {
+        Unit,
+        New(u8),
+    }
+    #[derive(Serialize)]
+    struct S {
+        a: vec::Vec<E>,
+        b: Option<f64>,
+        u: u64,
+    }
+    let s = S {
+        a: vec![E::Unit, E::New(4)],
+        b: None,
+        u: u64::MAX,
+    };
+    let mut v = super::to_value(&s).unwrap();
+    assert_eq!(v["a"][0], Value::from("Unit"));
+    assert_eq!(
+        v["a"][1],
+        Value::Tagged("New".into(), Value::from(4).into())
+    );
+    assert!(v["b"].is_null() && v["x"][3].is_null());
+    assert_eq!(super::to_string(&v).unwrap(), super::to_string(&s).unwrap());
+    v["c"]["d"] = 1.5.into();
+    v["a"][0] = vec![true, false].into();
+    let yaml =
+        "{ a: [ [ true, false ], !New 4 ], b: null, u: 18446744073709551615, c: { d: 1.5 } }";
+    assert_eq!(v.to_string(), yaml);
+    assert_eq!(serde_yaml::from_str::<Value>(yaml).unwrap(), v);
+    let json = serde_json::to_string(&v).unwrap();
+    assert_eq!(
+        serde_json::from_str::<Value>(&json).unwrap()["a"][1]["!New"],
+        Value::from(4)
+    );
+    let mut out = String::new();
+    let mut ser = super::CurlySerializer::new(&mut out);
+    ser.schema = super::Schema::JsonCompatible;
+    v.serialize(ser).unwrap();
+    let back = serde_json::from_str::<serde_json::Value>(&out).unwrap();
+    assert_eq!(back["a"][1], serde_json::json!({ "New": 4 }));
+
+    // Beyond what YAML parsers read back as integers
+    for i in [i128::MIN, i128::MAX] {
+        let out = super::to_string(&Value::Int(i)).unwrap();
+        assert_eq!(out.parse::<i128>().map(Value::Int).unwrap(), Value::Int(i));
+    }
+    assert_eq!(super::to_string(&u128::MAX).unwrap(), u128::MAX.to_string());
+}
+
+#[test]
+fn cyrly_macro() {
+    let name = "web";
+    let v = crate::cyrly!({
+        a: [1, -2.5, "b c", null, [], {}],
+        "b c": null,
+        null: true,
+        (format!("{name}-port")): { port: 8000 + 80, tls: !Off null, },
+        4: [name, !Some (1, 2)],
+    });
+    let out = v.to_string();
+    assert_eq!(
+        out,
+        "{ a: [ 1, -2.5, \"b c\", null, [], {} ], \"b c\": null, null: true, \
+        web-port: { port: 8080, tls: !Off null }, 4: [ web, !Some [ 1, 2 ] ] }"
+    );
+    assert_eq!(serde_yaml::from_str::<super::Value>(&out).unwrap(), v);
+
+    // Elements and entries take one level of recursion each, not one per token
+    let long = crate::cyrly!([
+        0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 17, 18, 19, 20, 21, 22, 23, 24,
+        25, 26, 27, 28, 29, 30, 31, 32, 33, 34, 35, 36, 37, 38, 39, 40, 41, 42, 43, 44, 45, 46, 47,
+        48, 49, 50, 51, 52, 53, 54, 55, 56, 57, 58, 59, 60, 61, 62, 63, 64, 65, 66, 67, 68, 69, 70,
+        71, 72, 73, 74, 75, 76, 77, 78, 79, 80, 81, 82, 83, 84, 85, 86, 87, 88, 89, 90, 91, 92, 93,
+        94, 95, 96, 97, 98, 99,
+    ]);
+    assert_eq!(long, (0..100).collect());
+    let mixed = crate::cyrly!([
+        !A [1, 2], null, { a: -1 }, name.len(),
+        !A [1, 2], null, { a: -1 }, name.len(),
+        !A [1, 2], null, { a: -1 }, name.len(),
+        !A [1, 2], null, { a: -1 }, name.len(),
+        !A [1, 2], null, { a: -1 }, name.len(),
+        !A [1, 2], null, { a: -1 }, name.len(),
+        !A [1, 2], null, { a: -1 }, name.len(),
+        !A [1, 2], null, { a: -1 }, name.len(),
+        !A [1, 2], null, { a: -1 }, name.len(),
+        !A [1, 2], null, { a: -1 }, name.len(),
+        !A [1, 2], null, { a: -1 }, name.len(),
+        !A [1, 2], null, { a: -1 }, name.len(),
+        !A [1, 2], null, { a: -1 }, name.len(),
+        !A [1, 2], null, { a: -1 }, name.len(),
+        !A [1, 2], null, { a: -1 }, name.len(),
+        !A [1, 2], null, { a: -1 }, name.len(),
+        !A [1, 2], null, { a: -1 }, name.len(),
+        !A [1, 2], null, { a: -1 }, name.len(),
+        !A [1, 2], null, { a: -1 }, name.len(),
+        !A [1, 2], null, { a: -1 }, name.len(),
+    ]);
+    assert_eq!(mixed.as_seq().unwrap()[79], super::Value::from(3));
+    let entries = crate::cyrly!({
+        k0: !On { b: [0] }, k1: null,
+        k2: !On { b: [2] }, k3: null,
+        k4: !On { b: [4] }, k5: null,
+        k6: !On { b: [6] }, k7: null,
+        k8: !On { b: [8] }, k9: null,
+        k10: !On { b: [10] }, k11: null,
+        k12: !On { b: [12] }, k13: null,
+        k14: !On { b: [14] }, k15: null,
+        k16: !On { b: [16] }, k17: null,
+        k18: !On { b: [18] }, k19: null,
+        k20: !On { b: [20] }, k21: null,
+        k22: !On { b: [22] }, k23: null,
+        k24: !On { b: [24] }, k25: null,
+        k26: !On { b: [26] }, k27: null,
+        k28: !On { b: [28] }, k29: null,
+        k30: !On { b: [30] }, k31: null,
+        k32: !On { b: [32] }, k33: null,
+        k34: !On { b: [34] }, k35: null,
+        k36: !On { b: [36] }, k37: null,
+        k38: !On { b: [38] }, k39: null,
+        k40: !On { b: [40] }, k41: null,
+        k42: !On { b: [42] }, k43: null,
+        k44: !On { b: [44] }, k45: null,
+        k46: !On { b: [46] }, k47: null,
+        k48: !On { b: [48] }, k49: null,
+        k50: !On { b: [50] }, k51: null,
+        k52: !On { b: [52] }, k53: null,
+        k54: !On { b: [54] }, k55: null,
+        k56: !On { b: [56] }, k57: null,
+        k58: !On { b: [58] }, k59: null,
+        k60: !On { b: [60] }, k61: null,
+        k62: !On { b: [62] }, k63: null,
+        k64: !On { b: [64] }, k65: null,
+        k66: !On { b: [66] }, k67: null,
+        k68: !On { b: [68] }, k69: null,
+        k70: !On { b: [70] }, k71: null,
+        k72: !On { b: [72] }, k73: null,
+        k74: !On { b: [74] }, k75: null,
+        k76: !On { b: [76] }, k77: null,
+        k78: !On { b: [78] }, k79: null,
+    });
+    assert_eq!(entries.as_map().unwrap().len(), 80);
+}
+
+#[test]
+fn diff() {
+    let a = crate::cyrly!({
+        name: "web",
+        ports: [80, 443],
+        env: [{ name: "A", value: 1 }, { name: "B", value: 2 }],
+        labels: { app: "web" },
+        old: true,
+    });
+    let b = crate::cyrly!({
+        name: "web",
+        ports: [8080, 443],
+        env: [{ name: "Z", value: [0] }, { name: "A", value: 1 }, { name: "B", value: 3 }],
+        labels: { app: "web" },
+        new: !Some "x",
+    });
+    assert_eq!(super::diff(&a, &a).unwrap(), "");
+    assert_eq!(
+        super::diff(&a, &b).unwrap(),
+        "  {
+    name: web,
+    ports: [
+-     80,
++     8080,
+      443,
+    ],
+    env: [
++     {
++       name: Z,
++       value: [
++         0,
++       ],
++     },
+      { … },
+      {
+        name: B,
+-       value: 2,
++       value: 3,
+      },
+    ],
+    labels: { … },
+-   old: true,
++   new: !Some x,
+  }
+"
+    );
+    // NaN isn't equal to itself, but it's no change either
+    let a = crate::cyrly!({ f: (f64::NAN), l: [(f64::NAN), 1], m: [{ g: (f64::NAN) }] });
+    let b = crate::cyrly!({ f: (f64::NAN), l: [(f64::NAN), 2], m: [{ g: (f64::NAN), h: 1 }] });
+    assert_eq!(super::diff(&a, &a).unwrap(), "");
+    assert_eq!(
+        super::diff(&a, &b).unwrap(),
+        "  {
+    f: .nan,
+    l: [
+      .nan,
+-     1,
++     2,
+    ],
+    m: [
+      {
+        g: .nan,
++       h: 1,
+      },
+    ],
+  }
+"
+    );
+}
+
+#[test]
+fn merge() {
+    use super::SeqMerge;
+    let base = crate::cyrly!({
+        labels: { app: "web", tier: "backend" },
+        containers: [{ name: "main", image: "a:1" }, { name: "side", image: "b" }],
+        tag: !Env { a: 1 },
+    });
+    let overlay = crate::cyrly!({
+        labels: { tier: null, env: "prod" },
+        containers: [{ name: "main", image: "a:2" }, { name: "log", image: "c" }],
+        tag: !Env { b: 2 },
+        new: { a: 1, b: null, c: { d: null }, e: [{ f: null }] },
+        labels2: { app: null },
+    });
+    let merged = |seqs| {
+        let mut v = base.clone();
+        v.merge(overlay.clone(), &seqs);
+        v
+    };
+    let labels = crate::cyrly!({ app: "web", env: "prod" });
+    let v = merged(SeqMerge::Replace);
+    assert_eq!(v["labels"], labels);
+    assert_eq!(v["containers"], overlay["containers"]);
+    assert_eq!(v["tag"], crate::cyrly!(!Env { a: 1, b: 2 }));
+    // Nothing to remove from where there was nothing
+    assert_eq!(v["new"], crate::cyrly!({ a: 1, c: {}, e: [{ f: null }] }));
+    assert_eq!(v["labels2"], crate::cyrly!({}));
+    let v = merged(SeqMerge::Append);
+    assert_eq!(v["containers"].as_seq().unwrap().len(), 4);
+    let v = merged(SeqMerge::ByKey("name".into()));
+    assert_eq!(
+        v["containers"],
+        crate::cyrly!([
+            { name: "main", image: "a:2" },
+            { name: "side", image: "b" },
+            { name: "log", image: "c" },
+        ])
+    );
+}
@@ -0,0 +1,1082 @@
+//! Owned document model, see [Value]
+
+use super::*;
+use alloc::{borrow::ToOwned, boxed::Box};
+use core::fmt;
+use serde::{
+    de::{
+        self, Deserialize, Deserializer, EnumAccess, MapAccess, SeqAccess, VariantAccess, Visitor,
+    },
+    ser::Impossible,
+};
+
+/// Any data cyrly can write, e.g. to edit a document without a type for it
+///
+/// Maps keep the order of their entries, and can have any [Value] as key.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub enum Value {
+    /// `null`
+    #[default]
+    Null,
+    /// `true` or `false`
+    Bool(bool),
+    /// Integer, anything that fits an `i64` or `u64`, and more
+    Int(i128),
+    /// Floating point number
+    Float(f64),
+    /// String
+    String(String),
+    /// Sequence
+    Seq(Vec<Value>),
+    /// Map, in order
+    Map(Vec<(Value, Value)>),
+    /// YAML tag on a value, e.g. `!Bar 42`, written like an enum variant
+    Tagged(String, Box<Value>),
+}
+
+static NULL: Value = Value::Null;
+
+impl Value {
+    /// Value of the entry with string key `key`, if this is a map that has one
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        match self {
+            Value::Map(map) => map
+                .iter()
+                .find(|(k, _)| k.as_str() == Some(key))
+                .map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    /// Like [Value::get]
+    pub fn get_mut(&mut self, key: &str) -> Option<&mut Value> {
+        match self {
+            Value::Map(map) => map
+                .iter_mut()
+                .find(|(k, _)| k.as_str() == Some(key))
+                .map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    /// Whether this is [Value::Null]
+    pub fn is_null(&self) -> bool {
+        matches!(self, Value::Null)
+    }
+
+    /// The bool, if this is one
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            Value::Bool(v) => Some(*v),
+            _ => None,
+        }
+    }
+
+    /// The integer, if this is one
+    pub fn as_int(&self) -> Option<i128> {
+        match self {
+            Value::Int(v) => Some(*v),
+            _ => None,
+        }
+    }
+
+    /// The number, if this is a float or an integer
+    pub fn as_float(&self) -> Option<f64> {
+        match self {
+            Value::Float(v) => Some(*v),
+            Value::Int(v) => Some(*v as f64),
+            _ => None,
+        }
+    }
+
+    /// The string, if this is one
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Value::String(v) => Some(v),
+            _ => None,
+        }
+    }
+
+    /// The elements, if this is a sequence
+    pub fn as_seq(&self) -> Option<&Vec<Value>> {
+        match self {
+            Value::Seq(v) => Some(v),
+            _ => None,
+        }
+    }
+
+    /// The entries, if this is a map
+    pub fn as_map(&self) -> Option<&Vec<(Value, Value)>> {
+        match self {
+            Value::Map(v) => Some(v),
+            _ => None,
+        }
+    }
+
+    /// Deep merge `overlay` into this, e.g. for a config that overrides parts of a base config
+    ///
+    /// Maps are merged entry by entry, with `null` in `overlay` removing the entry.
+    /// Sequences are combined as `seqs` says, tagged values with the same tag by what they tag,
+    /// anything else is replaced by `overlay`.
+    /// Maps from `overlay` that end up in this without anything to merge with are left without their `null` entries,
+    /// the same as if they had been merged into an empty map, but sequence elements are taken as they are.
+    pub fn merge(&mut self, overlay: Value, seqs: &SeqMerge) {
+        match (self, overlay) {
+            (Value::Map(base), Value::Map(overlay)) => {
+                for (key, value) in overlay {
+                    let i = base.iter().position(|(k, _)| *k == key);
+                    match (i, value) {
+                        (Some(i), Value::Null) => drop(base.remove(i)),
+                        (None, Value::Null) => (),
+                        (Some(i), value) => base[i].1.merge(value, seqs),
+                        (None, mut value) => {
+                            value.strip_nulls();
+                            base.push((key, value))
+                        }
+                    }
+                }
+            }
+            (Value::Seq(base), Value::Seq(overlay)) => match seqs {
+                SeqMerge::Replace => *base = overlay,
+                SeqMerge::Append => base.extend(overlay),
+                SeqMerge::ByKey(key) => {
+                    for value in overlay {
+                        let id = match value.get(key) {
+                            Some(id) if !id.is_null() => id,
+                            _ => {
+                                base.push(value);
+                                continue;
+                            }
+                        };
+                        match base.iter().position(|e| e.get(key) == Some(id)) {
+                            Some(i) => base[i].merge(value, seqs),
+                            None => base.push(value),
+                        }
+                    }
+                }
+            },
+            (Value::Tagged(tag, base), Value::Tagged(overlay_tag, overlay))
+                if *tag == overlay_tag =>
+            {
+                base.merge(*overlay, seqs)
+            }
+            (base, mut overlay) => {
+                overlay.strip_nulls();
+                *base = overlay
+            }
+        }
+    }
+
+    /// Remove `null` entries from maps, also nested ones, but not from maps in sequences
+    fn strip_nulls(&mut self) {
+        match self {
+            Value::Map(map) => {
+                map.retain(|(_, value)| !value.is_null());
+                for (_, value) in map {
+                    value.strip_nulls();
+                }
+            }
+            Value::Tagged(_, value) => value.strip_nulls(),
+            _ => (),
+        }
+    }
+}
+
+/// How [Value::merge] combines two sequences
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+#[non_exhaustive]
+pub enum SeqMerge {
+    /// Take the overlay's
+    #[default]
+    Replace,
+    /// The overlay's elements after the base's
+    Append,
+    /// Merge maps that have the same value for this key, e.g. `name` for Kubernetes containers,
+    /// and append the rest
+    ByKey(String),
+}
+
+/// Element `index`, or [Value::Null] if this isn't a sequence that long
+impl core::ops::Index<usize> for Value {
+    type Output = Value;
+
+    fn index(&self, index: usize) -> &Value {
+        match self {
+            Value::Seq(seq) => seq.get(index).unwrap_or(&NULL),
+            _ => &NULL,
+        }
+    }
+}
+
+/// Element `index`, panics if this isn't a sequence that long
+impl core::ops::IndexMut<usize> for Value {
+    fn index_mut(&mut self, index: usize) -> &mut Value {
+        match self {
+            Value::Seq(seq) => &mut seq[index],
+            _ => panic!("cannot index into non-sequence with {index}"),
+        }
+    }
+}
+
+/// Value for string key `key`, or [Value::Null] if this isn't a map with that key
+impl core::ops::Index<&str> for Value {
+    type Output = Value;
+
+    fn index(&self, key: &str) -> &Value {
+        self.get(key).unwrap_or(&NULL)
+    }
+}
+
+/// Value for string key `key`, inserted as [Value::Null] if it's missing, turning [Value::Null] into a map first
+///
+/// Panics on anything else that isn't a map.
+impl core::ops::IndexMut<&str> for Value {
+    fn index_mut(&mut self, key: &str) -> &mut Value {
+        if self.is_null() {
+            *self = Value::Map(Vec::new());
+        }
+        let map = match self {
+            Value::Map(map) => map,
+            _ => panic!("cannot index into non-map with {key:?}"),
+        };
+        let i = match map.iter().position(|(k, _)| k.as_str() == Some(key)) {
+            Some(i) => i,
+            None => {
+                map.push((Value::String(key.to_owned()), Value::Null));
+                map.len() - 1
+            }
+        };
+        &mut map[i].1
+    }
+}
+
+/// Written with [display], on one line, or multiline with `{:#}`
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&display(self), f)
+    }
+}
+
+macro_rules! from_int {
+    ($($t:ty)*) => {$(
+        impl From<$t> for Value {
+            fn from(v: $t) -> Self {
+                Value::Int(v.into())
+            }
+        }
+    )*};
+}
+from_int!(i8 i16 i32 i64 i128 u8 u16 u32 u64);
+
+impl From<isize> for Value {
+    fn from(v: isize) -> Self {
+        Value::Int(v as i128)
+    }
+}
+
+impl From<usize> for Value {
+    fn from(v: usize) -> Self {
+        Value::Int(v as i128)
+    }
+}
+
+impl From<bool> for Value {
+    fn from(v: bool) -> Self {
+        Value::Bool(v)
+    }
+}
+
+impl From<f32> for Value {
+    fn from(v: f32) -> Self {
+        Value::Float(v.into())
+    }
+}
+
+impl From<f64> for Value {
+    fn from(v: f64) -> Self {
+        Value::Float(v)
+    }
+}
+
+impl From<&str> for Value {
+    fn from(v: &str) -> Self {
+        Value::String(v.to_owned())
+    }
+}
+
+impl From<String> for Value {
+    fn from(v: String) -> Self {
+        Value::String(v)
+    }
+}
+
+impl From<char> for Value {
+    fn from(v: char) -> Self {
+        Value::String(v.into())
+    }
+}
+
+impl From<()> for Value {
+    fn from((): ()) -> Self {
+        Value::Null
+    }
+}
+
+impl<T: Into<Value>> From<Option<T>> for Value {
+    fn from(v: Option<T>) -> Self {
+        v.map_or(Value::Null, Into::into)
+    }
+}
+
+impl<T: Into<Value>> From<Vec<T>> for Value {
+    fn from(v: Vec<T>) -> Self {
+        Value::Seq(v.into_iter().map(Into::into).collect())
+    }
+}
+
+impl<T: Into<Value>> FromIterator<T> for Value {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        Value::Seq(iter.into_iter().map(Into::into).collect())
+    }
+}
+
+#[doc(hidden)]
+pub mod __private {
+    pub use alloc::vec;
+}
+
+/// Build a [Value] from cyrly's own flow syntax
+///
+/// ```
+/// let replicas = 3;
+/// let v = cyrly::cyrly!({ spec: { replicas: replicas, "app name": "web", ports: [80, 443], tls: !Off null } });
+/// assert_eq!(
+///     v.to_string(),
+///     r#"{ spec: { replicas: 3, "app name": web, ports: [ 80, 443 ], tls: !Off null } }"#
+/// );
+/// ```
+///
+/// Keys can be identifiers (strings), `null`, `true`, `false`, literals or anything else in parentheses,
+/// tags are identifiers after `!`.
+/// Other values are Rust expressions, converted by [to_value],
+/// so strings need quotes, and expressions with a `,` outside of brackets or starting with `!` need parentheses.
+/// In particular, a bare word as a value is a Rust identifier, not a string,
+/// so cyrly output like `{ name: web }` isn't valid input, it takes `{ name: "web" }`.
+///
+/// Like `serde_json::json!`, each element or entry takes a level of macro recursion,
+/// so very long sequences or maps may need a higher `#![recursion_limit]`.
+#[macro_export]
+macro_rules! cyrly {
+    (null) => {
+        $crate::Value::Null
+    };
+    ([ $($tt:tt)* ]) => {
+        $crate::Value::Seq($crate::cyrly!(@seq [] [] $($tt)*))
+    };
+    ({ $($tt:tt)* }) => {
+        $crate::Value::Map($crate::cyrly!(@map [] $($tt)*))
+    };
+    (! $tag:ident $($value:tt)+) => {
+        $crate::Value::Tagged(
+            ::core::convert::From::from(::core::stringify!($tag)),
+            ::core::convert::From::from($crate::cyrly!($($value)+)),
+        )
+    };
+
+    // Elements, whole if they can be, otherwise collecting tokens up to the next `,` in [$($cur)*]
+    (@seq [$($done:expr,)*] []) => {
+        $crate::__private::vec![$($done,)*]
+    };
+    (@seq [$($done:expr,)*] [] null $(, $($rest:tt)*)?) => {
+        $crate::cyrly!(@seq [$($done,)* $crate::Value::Null,] [] $($($rest)*)?)
+    };
+    (@seq [$($done:expr,)*] [] [$($seq:tt)*] $(, $($rest:tt)*)?) => {
+        $crate::cyrly!(@seq [$($done,)* $crate::cyrly!([$($seq)*]),] [] $($($rest)*)?)
+    };
+    (@seq [$($done:expr,)*] [] {$($map:tt)*} $(, $($rest:tt)*)?) => {
+        $crate::cyrly!(@seq [$($done,)* $crate::cyrly!({$($map)*}),] [] $($($rest)*)?)
+    };
+    (@seq [$($done:expr,)*] [] ! $tag:ident $value:tt $(, $($rest:tt)*)?) => {
+        $crate::cyrly!(@seq [$($done,)* $crate::cyrly!(! $tag $value),] [] $($($rest)*)?)
+    };
+    (@seq [$($done:expr,)*] [] ! $($rest:tt)*) => {
+        $crate::cyrly!(@seq [$($done,)*] [!] $($rest)*)
+    };
+    (@seq [$($done:expr,)*] [] $next:expr $(, $($rest:tt)*)?) => {
+        $crate::cyrly!(@seq [$($done,)* $crate::cyrly!($next),] [] $($($rest)*)?)
+    };
+    (@seq [$($done:expr,)*] [$($cur:tt)+]) => {
+        $crate::cyrly!(@seq [$($done,)* $crate::cyrly!($($cur)+),] [])
+    };
+    (@seq [$($done:expr,)*] [$($cur:tt)+] , $($rest:tt)*) => {
+        $crate::cyrly!(@seq [$($done,)* $crate::cyrly!($($cur)+),] [] $($rest)*)
+    };
+    (@seq [$($done:expr,)*] [$($cur:tt)*] $next:tt $($rest:tt)*) => {
+        $crate::cyrly!(@seq [$($done,)*] [$($cur)* $next] $($rest)*)
+    };
+
+    // Entries, the same for values after `key:`
+    (@map [$($done:expr,)*]) => {
+        $crate::__private::vec![$($done,)*]
+    };
+    (@map [$($done:expr,)*] $key:tt : null $(, $($rest:tt)*)?) => {
+        $crate::cyrly!(@map [$($done,)* ($crate::cyrly!(@key $key), $crate::Value::Null),] $($($rest)*)?)
+    };
+    (@map [$($done:expr,)*] $key:tt : [$($seq:tt)*] $(, $($rest:tt)*)?) => {
+        $crate::cyrly!(@map [$($done,)* ($crate::cyrly!(@key $key), $crate::cyrly!([$($seq)*])),] $($($rest)*)?)
+    };
+    (@map [$($done:expr,)*] $key:tt : {$($map:tt)*} $(, $($rest:tt)*)?) => {
+        $crate::cyrly!(@map [$($done,)* ($crate::cyrly!(@key $key), $crate::cyrly!({$($map)*})),] $($($rest)*)?)
+    };
+    (@map [$($done:expr,)*] $key:tt : ! $tag:ident $value:tt $(, $($rest:tt)*)?) => {
+        $crate::cyrly!(@map [$($done,)* ($crate::cyrly!(@key $key), $crate::cyrly!(! $tag $value)),] $($($rest)*)?)
+    };
+    (@map [$($done:expr,)*] $key:tt : ! $($rest:tt)*) => {
+        $crate::cyrly!(@entry [$($done,)*] ($crate::cyrly!(@key $key)) [!] $($rest)*)
+    };
+    (@map [$($done:expr,)*] $key:tt : $value:expr $(, $($rest:tt)*)?) => {
+        $crate::cyrly!(@map [$($done,)* ($crate::cyrly!(@key $key), $crate::cyrly!($value)),] $($($rest)*)?)
+    };
+    (@map [$($done:expr,)*] $key:tt : $($rest:tt)*) => {
+        $crate::cyrly!(@entry [$($done,)*] ($crate::cyrly!(@key $key)) [] $($rest)*)
+    };
+    (@entry [$($done:expr,)*] ($key:expr) [$($cur:tt)+]) => {
+        $crate::cyrly!(@map [$($done,)* ($key, $crate::cyrly!($($cur)+)),])
+    };
+    (@entry [$($done:expr,)*] ($key:expr) [$($cur:tt)+] , $($rest:tt)*) => {
+        $crate::cyrly!(@map [$($done,)* ($key, $crate::cyrly!($($cur)+)),] $($rest)*)
+    };
+    (@entry [$($done:expr,)*] ($key:expr) [$($cur:tt)*] $next:tt $($rest:tt)*) => {
+        $crate::cyrly!(@entry [$($done,)*] ($key) [$($cur)* $next] $($rest)*)
+    };
+    (@key null) => {
+        $crate::Value::Null
+    };
+    (@key true) => {
+        $crate::Value::Bool(true)
+    };
+    (@key false) => {
+        $crate::Value::Bool(false)
+    };
+    (@key $key:ident) => {
+        $crate::Value::String(::core::convert::From::from(::core::stringify!($key)))
+    };
+    (@key $key:tt) => {
+        $crate::cyrly!($key)
+    };
+
+    ($other:expr) => {
+        $crate::to_value(&$other).unwrap()
+    };
+}
+
+/// Name of the newtype struct [Value::Tagged] serializes as, so [CurlySerializer] can tell it apart
+pub(crate) const TAGGED: &str = "$cyrly::Tagged";
+
+/// `{ "!tag": value }`, which is what other serializers see of [Value::Tagged]
+struct TaggedEntry<'a>(&'a str, &'a Value);
+
+impl Serialize for TaggedEntry<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(Some(1))?;
+        map.serialize_entry(&format_args!("!{}", self.0), self.1)?;
+        map.end()
+    }
+}
+
+impl Serialize for Value {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            Value::Null => serializer.serialize_unit(),
+            Value::Bool(v) => serializer.serialize_bool(*v),
+            Value::Int(v) => match (i64::try_from(*v), u64::try_from(*v)) {
+                (Ok(v), _) => serializer.serialize_i64(v),
+                (_, Ok(v)) => serializer.serialize_u64(v),
+                _ => serializer.serialize_i128(*v),
+            },
+            Value::Float(v) => serializer.serialize_f64(*v),
+            Value::String(v) => serializer.serialize_str(v),
+            Value::Seq(v) => serializer.collect_seq(v),
+            Value::Map(v) => serializer.collect_map(v.iter().map(|(k, v)| (k, v))),
+            Value::Tagged(tag, v) => {
+                serializer.serialize_newtype_struct(TAGGED, &TaggedEntry(tag, v))
+            }
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Value {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_any(ValueVisitor)
+    }
+}
+
+struct ValueVisitor;
+
+impl<'de> Visitor<'de> for ValueVisitor {
+    type Value = Value;
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("any value")
+    }
+
+    fn visit_bool<Er: de::Error>(self, v: bool) -> Result<Value, Er> {
+        Ok(Value::Bool(v))
+    }
+
+    fn visit_i64<Er: de::Error>(self, v: i64) -> Result<Value, Er> {
+        Ok(Value::Int(v.into()))
+    }
+
+    fn visit_u64<Er: de::Error>(self, v: u64) -> Result<Value, Er> {
+        Ok(Value::Int(v.into()))
+    }
+
+    fn visit_i128<Er: de::Error>(self, v: i128) -> Result<Value, Er> {
+        Ok(Value::Int(v))
+    }
+
+    fn visit_u128<Er: de::Error>(self, v: u128) -> Result<Value, Er> {
+        match i128::try_from(v) {
+            Ok(v) => Ok(Value::Int(v)),
+            Err(_) => Err(Er::custom("integer out of range")),
+        }
+    }
+
+    fn visit_f64<Er: de::Error>(self, v: f64) -> Result<Value, Er> {
+        Ok(Value::Float(v))
+    }
+
+    fn visit_str<Er: de::Error>(self, v: &str) -> Result<Value, Er> {
+        Ok(Value::String(v.to_owned()))
+    }
+
+    fn visit_string<Er: de::Error>(self, v: String) -> Result<Value, Er> {
+        Ok(Value::String(v))
+    }
+
+    fn visit_bytes<Er: de::Error>(self, v: &[u8]) -> Result<Value, Er> {
+        Ok(v.iter().copied().collect())
+    }
+
+    fn visit_none<Er: de::Error>(self) -> Result<Value, Er> {
+        Ok(Value::Null)
+    }
+
+    fn visit_unit<Er: de::Error>(self) -> Result<Value, Er> {
+        Ok(Value::Null)
+    }
+
+    fn visit_some<D: Deserializer<'de>>(self, deserializer: D) -> Result<Value, D::Error> {
+        Value::deserialize(deserializer)
+    }
+
+    fn visit_newtype_struct<D: Deserializer<'de>>(
+        self,
+        deserializer: D,
+    ) -> Result<Value, D::Error> {
+        Value::deserialize(deserializer)
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Value, A::Error> {
+        let mut v = Vec::with_capacity(seq.size_hint().unwrap_or(0).min(4096));
+        while let Some(e) = seq.next_element()? {
+            v.push(e);
+        }
+        Ok(Value::Seq(v))
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Value, A::Error> {
+        let mut v = Vec::with_capacity(map.size_hint().unwrap_or(0).min(4096));
+        while let Some(e) = map.next_entry()? {
+            v.push(e);
+        }
+        Ok(Value::Map(v))
+    }
+
+    fn visit_enum<A: EnumAccess<'de>>(self, data: A) -> Result<Value, A::Error> {
+        let (tag, variant) = data.variant::<String>()?;
+        Ok(Value::Tagged(tag, Box::new(variant.newtype_variant()?)))
+    }
+}
+
+/// Convert anything serializable into a [Value]
+///
+/// Enum variants with data become [Value::Tagged], unit variants strings, like they are written.
+pub fn to_value<T: Serialize + ?Sized>(value: &T) -> Result<Value, Error> {
+    value.serialize(ValueSerializer)
+}
+
+struct ValueSerializer;
+
+impl Serializer for ValueSerializer {
+    type Ok = Value;
+    type Error = Error;
+    type SerializeSeq = SerializeVec;
+    type SerializeTuple = SerializeVec;
+    type SerializeTupleStruct = SerializeVec;
+    type SerializeTupleVariant = SerializeVec;
+    type SerializeMap = SerializeEntries;
+    type SerializeStruct = SerializeEntries;
+    type SerializeStructVariant = SerializeEntries;
+
+    fn serialize_bool(self, v: bool) -> Result<Value, Error> {
+        Ok(Value::Bool(v))
+    }
+
+    fn serialize_i8(self, v: i8) -> Result<Value, Error> {
+        Ok(v.into())
+    }
+
+    fn serialize_i16(self, v: i16) -> Result<Value, Error> {
+        Ok(v.into())
+    }
+
+    fn serialize_i32(self, v: i32) -> Result<Value, Error> {
+        Ok(v.into())
+    }
+
+    fn serialize_i64(self, v: i64) -> Result<Value, Error> {
+        Ok(v.into())
+    }
+
+    fn serialize_i128(self, v: i128) -> Result<Value, Error> {
+        Ok(v.into())
+    }
+
+    fn serialize_u8(self, v: u8) -> Result<Value, Error> {
+        Ok(v.into())
+    }
+
+    fn serialize_u16(self, v: u16) -> Result<Value, Error> {
+        Ok(v.into())
+    }
+
+    fn serialize_u32(self, v: u32) -> Result<Value, Error> {
+        Ok(v.into())
+    }
+
+    fn serialize_u64(self, v: u64) -> Result<Value, Error> {
+        Ok(v.into())
+    }
+
+    fn serialize_u128(self, v: u128) -> Result<Value, Error> {
+        match i128::try_from(v) {
+            Ok(v) => Ok(Value::Int(v)),
+            Err(_) => Err(Error::new(ErrorKind::Unsupported, "integer out of range")),
+        }
+    }
+
+    fn serialize_f32(self, v: f32) -> Result<Value, Error> {
+        Ok(v.into())
+    }
+
+    fn serialize_f64(self, v: f64) -> Result<Value, Error> {
+        Ok(v.into())
+    }
+
+    fn serialize_char(self, v: char) -> Result<Value, Error> {
+        Ok(v.into())
+    }
+
+    fn serialize_str(self, v: &str) -> Result<Value, Error> {
+        Ok(v.into())
+    }
+
+    fn serialize_bytes(self, v: &[u8]) -> Result<Value, Error> {
+        Ok(v.iter().copied().collect())
+    }
+
+    fn serialize_none(self) -> Result<Value, Error> {
+        Ok(Value::Null)
+    }
+
+    fn serialize_some<T: ?Sized + Serialize>(self, value: &T) -> Result<Value, Error> {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<Value, Error> {
+        Ok(Value::Null)
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<Value, Error> {
+        Ok(Value::Null)
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+    ) -> Result<Value, Error> {
+        Ok(variant.into())
+    }
+
+    fn serialize_newtype_struct<T: ?Sized + Serialize>(
+        self,
+        name: &'static str,
+        value: &T,
+    ) -> Result<Value, Error> {
+        let value = value.serialize(self)?;
+        if name != TAGGED {
+            return Ok(value);
+        }
+        match value {
+            Value::Map(map) if map.len() == 1 => {
+                let (tag, value) = map.into_iter().next().expect("one entry");
+                match tag {
+                    Value::String(tag) if tag.starts_with('!') => {
+                        Ok(Value::Tagged(tag[1..].to_owned(), Box::new(value)))
+                    }
+                    _ => Err(Error::new(ErrorKind::Unsupported, "tag must be a string")),
+                }
+            }
+            _ => Err(Error::new(ErrorKind::Unsupported, "tag must be a string")),
+        }
+    }
+
+    fn serialize_newtype_variant<T: ?Sized + Serialize>(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        value: &T,
+    ) -> Result<Value, Error> {
+        let value = value.serialize(self)?;
+        Ok(Value::Tagged(variant.to_owned(), Box::new(value)))
+    }
+
+    fn serialize_seq(self, len: Option<usize>) -> Result<SerializeVec, Error> {
+        Ok(SerializeVec {
+            variant: None,
+            seq: Vec::with_capacity(len.unwrap_or(0).min(4096)),
+        })
+    }
+
+    fn serialize_tuple(self, len: usize) -> Result<SerializeVec, Error> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        _name: &'static str,
+        len: usize,
+    ) -> Result<SerializeVec, Error> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> Result<SerializeVec, Error> {
+        let mut seq = self.serialize_seq(Some(len))?;
+        seq.variant = Some(variant);
+        Ok(seq)
+    }
+
+    fn serialize_map(self, len: Option<usize>) -> Result<SerializeEntries, Error> {
+        Ok(SerializeEntries {
+            variant: None,
+            map: Vec::with_capacity(len.unwrap_or(0).min(4096)),
+            key: None,
+        })
+    }
+
+    fn serialize_struct(self, _name: &'static str, len: usize) -> Result<SerializeEntries, Error> {
+        self.serialize_map(Some(len))
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> Result<SerializeEntries, Error> {
+        let mut map = self.serialize_map(Some(len))?;
+        map.variant = Some(variant);
+        Ok(map)
+    }
+}
+
+/// Wrap `value` in [Value::Tagged] if it's for an enum variant
+fn tag(variant: Option<&'static str>, value: Value) -> Value {
+    match variant {
+        Some(tag) => Value::Tagged(tag.to_owned(), Box::new(value)),
+        None => value,
+    }
+}
+
+struct SerializeVec {
+    variant: Option<&'static str>,
+    seq: Vec<Value>,
+}
+
+impl SerializeSeq for SerializeVec {
+    type Ok = Value;
+    type Error = Error;
+
+    fn serialize_element<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<(), Error> {
+        self.seq.push(value.serialize(ValueSerializer)?);
+        Ok(())
+    }
+
+    fn end(self) -> Result<Value, Error> {
+        Ok(tag(self.variant, Value::Seq(self.seq)))
+    }
+}
+
+impl SerializeTuple for SerializeVec {
+    type Ok = Value;
+    type Error = Error;
+
+    fn serialize_element<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<(), Error> {
+        SerializeSeq::serialize_element(self, value)
+    }
+
+    fn end(self) -> Result<Value, Error> {
+        SerializeSeq::end(self)
+    }
+}
+
+impl SerializeTupleStruct for SerializeVec {
+    type Ok = Value;
+    type Error = Error;
+
+    fn serialize_field<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<(), Error> {
+        SerializeSeq::serialize_element(self, value)
+    }
+
+    fn end(self) -> Result<Value, Error> {
+        SerializeSeq::end(self)
+    }
+}
+
+impl SerializeTupleVariant for SerializeVec {
+    type Ok = Value;
+    type Error = Error;
+
+    fn serialize_field<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<(), Error> {
+        SerializeSeq::serialize_element(self, value)
+    }
+
+    fn end(self) -> Result<Value, Error> {
+        SerializeSeq::end(self)
+    }
+}
+
+struct SerializeEntries {
+    variant: Option<&'static str>,
+    map: Vec<(Value, Value)>,
+    key: Option<Value>,
+}
+
+impl SerializeMap for SerializeEntries {
+    type Ok = Value;
+    type Error = Error;
+
+    fn serialize_key<T: ?Sized + Serialize>(&mut self, key: &T) -> Result<(), Error> {
+        self.key = Some(key.serialize(ValueSerializer)?);
+        Ok(())
+    }
+
+    fn serialize_value<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<(), Error> {
+        let key = self.key.take().unwrap_or_default();
+        self.map.push((key, value.serialize(ValueSerializer)?));
+        Ok(())
+    }
+
+    fn end(self) -> Result<Value, Error> {
+        Ok(tag(self.variant, Value::Map(self.map)))
+    }
+}
+
+impl SerializeStruct for SerializeEntries {
+    type Ok = Value;
+    type Error = Error;
+
+    fn serialize_field<T: ?Sized + Serialize>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), Error> {
+        SerializeMap::serialize_entry(self, key, value)
+    }
+
+    fn end(self) -> Result<Value, Error> {
+        SerializeMap::end(self)
+    }
+}
+
+impl SerializeStructVariant for SerializeEntries {
+    type Ok = Value;
+    type Error = Error;
+
+    fn serialize_field<T: ?Sized + Serialize>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), Error> {
+        SerializeMap::serialize_entry(self, key, value)
+    }
+
+    fn end(self) -> Result<Value, Error> {
+        SerializeMap::end(self)
+    }
+}
+
+/// Takes the [TaggedEntry] apart again, to write [Value::Tagged] like an enum variant
+pub(crate) struct TagSerializer<'e, E>(pub(crate) CurlySerializer<'e, E>);
+
+/// Anything but the map of a [TaggedEntry]
+fn not_tagged<E: Eat>() -> E::Error {
+    E::error(ErrorKind::Unsupported, "internal: not a tagged value")
+}
+
+macro_rules! not_tagged {
+    ($($f:ident($($t:ty),*)),*) => {$(
+        fn $f(self, $(_: $t),*) -> Result<(), E::Error> {
+            Err(not_tagged::<E>())
+        }
+    )*};
+}
+
+impl<'e, E: Eat> Serializer for TagSerializer<'e, E> {
+    type Ok = ();
+    type Error = E::Error;
+    type SerializeSeq = Impossible<(), E::Error>;
+    type SerializeTuple = Impossible<(), E::Error>;
+    type SerializeTupleStruct = Impossible<(), E::Error>;
+    type SerializeTupleVariant = Impossible<(), E::Error>;
+    type SerializeMap = TagMap<'e, E>;
+    type SerializeStruct = Impossible<(), E::Error>;
+    type SerializeStructVariant = Impossible<(), E::Error>;
+
+    not_tagged!(
+        serialize_bool(bool),
+        serialize_i8(i8),
+        serialize_i16(i16),
+        serialize_i32(i32),
+        serialize_i64(i64),
+        serialize_u8(u8),
+        serialize_u16(u16),
+        serialize_u32(u32),
+        serialize_u64(u64),
+        serialize_f32(f32),
+        serialize_f64(f64),
+        serialize_char(char),
+        serialize_str(&str),
+        serialize_bytes(&[u8]),
+        serialize_none(),
+        serialize_unit(),
+        serialize_unit_struct(&'static str),
+        serialize_unit_variant(&'static str, u32, &'static str)
+    );
+
+    fn serialize_some<T: ?Sized + Serialize>(self, _: &T) -> Result<(), E::Error> {
+        Err(not_tagged::<E>())
+    }
+
+    fn serialize_newtype_struct<T: ?Sized + Serialize>(
+        self,
+        _: &'static str,
+        _: &T,
+    ) -> Result<(), E::Error> {
+        Err(not_tagged::<E>())
+    }
+
+    fn serialize_newtype_variant<T: ?Sized + Serialize>(
+        self,
+        _: &'static str,
+        _: u32,
+        _: &'static str,
+        _: &T,
+    ) -> Result<(), E::Error> {
+        Err(not_tagged::<E>())
+    }
+
+    fn serialize_seq(self, _: Option<usize>) -> Result<Self::SerializeSeq, E::Error> {
+        Err(not_tagged::<E>())
+    }
+
+    fn serialize_tuple(self, _: usize) -> Result<Self::SerializeTuple, E::Error> {
+        Err(not_tagged::<E>())
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        _: &'static str,
+        _: usize,
+    ) -> Result<Self::SerializeTupleStruct, E::Error> {
+        Err(not_tagged::<E>())
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _: &'static str,
+        _: u32,
+        _: &'static str,
+        _: usize,
+    ) -> Result<Self::SerializeTupleVariant, E::Error> {
+        Err(not_tagged::<E>())
+    }
+
+    fn serialize_map(self, _: Option<usize>) -> Result<TagMap<'e, E>, E::Error> {
+        Ok(TagMap {
+            ser: Some(self.0),
+            tag: None,
+        })
+    }
+
+    fn serialize_struct(
+        self,
+        _: &'static str,
+        _: usize,
+    ) -> Result<Self::SerializeStruct, E::Error> {
+        Err(not_tagged::<E>())
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _: &'static str,
+        _: u32,
+        _: &'static str,
+        _: usize,
+    ) -> Result<Self::SerializeStructVariant, E::Error> {
+        Err(not_tagged::<E>())
+    }
+}
+
+pub(crate) struct TagMap<'e, E> {
+    ser: Option<CurlySerializer<'e, E>>,
+    tag: Option<String>,
+}
+
+impl<E: Eat> SerializeMap for TagMap<'_, E> {
+    type Ok = ();
+    type Error = E::Error;
+
+    fn serialize_key<T: ?Sized + Serialize>(&mut self, key: &T) -> Result<(), E::Error> {
+        match to_value(key) {
+            Ok(Value::String(tag)) if tag.starts_with('!') => {
+                self.tag = Some(tag[1..].to_owned());
+                Ok(())
+            }
+            _ => Err(not_tagged::<E>()),
+        }
+    }
+
+    fn serialize_value<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<(), E::Error> {
+        match (self.ser.take(), self.tag.take()) {
+            (Some(ser), Some(tag)) => ser.serialize_tagged(&tag, value),
+            _ => Err(not_tagged::<E>()),
+        }
+    }
+
+    fn end(self) -> Result<(), E::Error> {
+        Ok(())
+    }
+}